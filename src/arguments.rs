@@ -0,0 +1,131 @@
+use std::env;
+
+/// Declaration of a command line option, help text is generated from it.
+pub struct OptionSpec {
+    pub long: &'static str,
+    pub short: Option<char>,
+    /// Old single dash spellings like `-dry`, kept for existing scripts.
+    pub aliases: &'static [&'static str],
+    /// Name of the value shown in help, None for flags.
+    pub value: Option<&'static str>,
+    /// Environment variable with the default value, used if the option is not given.
+    pub env: Option<&'static str>,
+    /// Lines of the description.
+    pub help: &'static [&'static str],
+}
+
+pub struct ParsedArguments {
+    /// Long names of the given options with their values, in the order given,
+    /// preceded by the ones defaulted from environment variables, with the variable.
+    pub options: Vec<(&'static str, Option<String>, Option<&'static str>)>,
+    pub positional: Vec<String>,
+}
+
+/// Parses `--long VALUE`, `--long=VALUE`, `-s VALUE`, `-sVALUE`, combined flags like `-nr`,
+/// old spellings and positional arguments, everything after `--` is positional.
+/// Returns a description of the problem for unknown options and missing values.
+pub fn parse(specs: &'static [OptionSpec], args: impl Iterator<Item=String>) -> Result<ParsedArguments, String> {
+    let mut args = args;
+    let mut options: Vec<(&'static str, Option<String>)> = Vec::new();
+    let mut positional: Vec<String> = Vec::new();
+    let mut only_positional: bool = false;
+    while let Some(arg) = args.next() {
+        if only_positional || arg == "-" || !arg.starts_with('-') {
+            positional.push(arg);
+            continue;
+        }
+        if arg == "--" {
+            only_positional = true;
+            continue;
+        }
+        // old spellings look like combined flags, so they are matched first:
+        if let Some(spec) = specs.iter().find(|s| s.aliases.contains(&arg.as_str())) {
+            options.push((spec.long, take_value(spec, None, &mut args)?));
+            continue;
+        }
+        if let Some(long) = arg.strip_prefix("--") {
+            let (name, inline) = match long.split_once('=') {
+                Some((name, value)) => (name, Some(value.to_string())),
+                None => (long, None)
+            };
+            let spec = specs.iter().find(|s| s.long == name)
+                .ok_or_else(|| format!("Unrecognized argument: {}", arg))?;
+            if spec.value.is_none() && inline.is_some() {
+                return Err(format!("--{} does not take a value", spec.long));
+            }
+            options.push((spec.long, take_value(spec, inline, &mut args)?));
+            continue;
+        }
+        let cluster: &str = &arg[1..];
+        for (i, c) in cluster.char_indices() {
+            let spec = specs.iter().find(|s| s.short == Some(c))
+                .ok_or_else(|| format!("Unrecognized argument: {}", arg))?;
+            if spec.value.is_some() {
+                // rest of the cluster is the value, like -j4:
+                let rest: &str = &cluster[i + c.len_utf8()..];
+                let inline = if rest.is_empty() { None } else { Some(rest.to_string()) };
+                options.push((spec.long, take_value(spec, inline, &mut args)?));
+                break;
+            }
+            options.push((spec.long, None));
+        }
+    }
+
+    let mut defaults: Vec<(&'static str, Option<String>, Option<&'static str>)> = Vec::new();
+    for spec in specs {
+        let var = match spec.env {
+            Some(var) if !options.iter().any(|(long, _)| *long == spec.long) => var,
+            _ => continue
+        };
+        match env::var(var) {
+            Ok(value) if !value.is_empty() => defaults.push((spec.long, Some(value), Some(var))),
+            _ => {}
+        }
+    }
+    defaults.extend(options.into_iter().map(|(long, value)| (long, value, None)));
+    return Ok(ParsedArguments {
+        options: defaults,
+        positional,
+    });
+}
+
+fn take_value(spec: &OptionSpec,
+              inline: Option<String>,
+              args: &mut impl Iterator<Item=String>) -> Result<Option<String>, String> {
+    let name = match spec.value {
+        Some(name) => name,
+        None => return Ok(None)
+    };
+    // values may start with a dash, like -shift -1h:
+    return match inline.or_else(|| args.next()) {
+        Some(value) => Ok(Some(value)),
+        None => Err(format!("--{} requires a value: {}", spec.long, name))
+    };
+}
+
+/// Options section of the help, in the order of declaration.
+pub fn format_help(specs: &[OptionSpec]) -> String {
+    let mut help: String = String::new();
+    for spec in specs {
+        let mut usage: String = match spec.short {
+            Some(short) => format!("    -{}, --{}", short, spec.long),
+            None => format!("    --{}", spec.long)
+        };
+        if let Some(value) = spec.value {
+            usage = format!("{} {}", usage, value);
+        }
+        let mut lines: Vec<String> = spec.help.iter().map(|l| l.to_string()).collect();
+        if !spec.aliases.is_empty() {
+            lines.push(format!("Also: {}.", spec.aliases.join(", ")));
+        }
+        if let Some(var) = spec.env {
+            lines.push(format!("Default from environment variable {}.", var));
+        }
+        help.push_str(&usage);
+        help.push('\n');
+        for line in lines {
+            help.push_str(&format!("{:16}{}\n", "", line));
+        }
+    }
+    return help;
+}
@@ -1,73 +1,777 @@
+#![allow(clippy::needless_return)]
+
 use std::env;
+use std::path::PathBuf;
 use std::process;
+use std::sync::Mutex;
 
+mod arguments;
 mod timestampname;
 
+use arguments::OptionSpec;
+use timestampname::failures::Failure;
+use timestampname::failures::FailureKind;
+
+// nothing was renamed:
+const EXIT_FAILURE: i32 = 1;
+// only possible with --keep-going, some files were renamed and some failed:
+const EXIT_PARTIAL_SUCCESS: i32 = 2;
+
+const OPTIONS: &[OptionSpec] = &[
+    OptionSpec {
+        long: "help",
+        short: Some('h'),
+        aliases: &[],
+        value: None,
+        env: None,
+        help: &["Display help and exit."],
+    },
+    OptionSpec {
+        long: "version",
+        short: Some('V'),
+        aliases: &[],
+        value: None,
+        env: None,
+        help: &["Display version and exit."],
+    },
+    OptionSpec {
+        long: "dry-run",
+        short: Some('n'),
+        aliases: &["-dry"],
+        value: None,
+        env: None,
+        help: &["Only show the operations but do not perform a rename."],
+    },
+    OptionSpec {
+        long: "debug",
+        short: None,
+        aliases: &["-debug"],
+        value: None,
+        env: None,
+        help: &["Enable debug output."],
+    },
+    OptionSpec {
+        long: "no-prefix",
+        short: None,
+        aliases: &["-noprefix"],
+        value: None,
+        env: None,
+        help: &[
+            "Do not add numerical prefix to the renamed files",
+            "(works if not more than one file is shot per second).",
+        ],
+    },
+    OptionSpec {
+        long: "millis",
+        short: None,
+        aliases: &["-millis"],
+        value: None,
+        env: None,
+        help: &[
+            "Add milliseconds to the timestamp in file names, taken from",
+            "EXIF SubSecTime tags (000 if not available). Together with",
+            "--no-prefix works for burst shots within the same second.",
+        ],
+    },
+    OptionSpec {
+        long: "template",
+        short: Some('t'),
+        aliases: &[],
+        value: Some("TEMPLATE"),
+        env: Some("TIMESTAMPNAME_TEMPLATE"),
+        help: &[
+            "Pattern of the new file names, default is {counter}-{date}{ext}.",
+            "Text is kept as is, {{ and }} are literal braces. Placeholders:",
+            "{counter}   position in the sorted sequence, padded to the width",
+            "            of the largest one, {counter:4} pads to 4 digits,",
+            "{date}      timestamp as 20180430-184327, {date:%Y-%m-%d_%H%M}",
+            "            takes a strftime format,",
+            "{subsec}    milliseconds, {subsec:6} for other number of digits,",
+            "{offset}    time zone offset like +0200, empty if unknown,",
+            "{stem}      original file name without extension,",
+            "{make}, {model}, {camera}, {lens}",
+            "            EXIF Make, Model, both of them and LensModel,",
+            "            empty if unknown,",
+            "{ext}       extension with the dot, lowercase, {ext:upper} or",
+            "            {ext:keep} for other cases.",
+            "{counter}, {date} or {stem} is required, path separators are",
+            "not allowed. Cannot be combined with --no-prefix or --millis.",
+        ],
+    },
+    OptionSpec {
+        long: "dest",
+        short: Some('d'),
+        aliases: &[],
+        value: Some("DIR"),
+        env: None,
+        help: &[
+            "Move files into DIR instead of renaming them in place, with",
+            "counters per destination directory, continuing after the",
+            "highest counter of names already there that match",
+            "--template. Counters are padded to at least 4 digits, or to",
+            "the widest counter there, so that names of later runs sort",
+            "in order. Files on another file system are copied and",
+            "removed.",
+        ],
+    },
+    OptionSpec {
+        long: "copy",
+        short: Some('c'),
+        aliases: &[],
+        value: None,
+        env: None,
+        help: &[
+            "Copy files into --dest instead of moving them, sources are",
+            "left untouched. Every copy keeps the modification time and",
+            "is compared with its source by SHA-256 checksum, failed",
+            "copies are removed. On Linux file systems that support it,",
+            "like Btrfs and XFS, copies share the blocks of their sources",
+            "instead of duplicating them.",
+        ],
+    },
+    OptionSpec {
+        long: "delete-source",
+        short: None,
+        aliases: &[],
+        value: None,
+        env: None,
+        help: &["Delete every source file after its copy was verified."],
+    },
+    OptionSpec {
+        long: "link",
+        short: Some('l'),
+        aliases: &[],
+        value: Some("KIND"),
+        env: None,
+        help: &[
+            "Create links in --dest instead of moving files, sources keep",
+            "their names. KIND is 'hard' or 'symbolic' (links to absolute",
+            "paths). Running again updates the view: links made by this",
+            "tool are made again, so that files added later are numbered",
+            "in order with them. Other links to the files are kept.",
+        ],
+    },
+    OptionSpec {
+        long: "prune",
+        short: None,
+        aliases: &[],
+        value: None,
+        env: None,
+        help: &[
+            "Remove links in --dest whose sources are gone, with --link.",
+            "Only links made by this tool are removed, they are recorded",
+            "in .timestampname-links of --dest. The links are listed,",
+            "also with --dry-run, and removed after confirmation.",
+        ],
+    },
+    OptionSpec {
+        long: "dir-template",
+        short: None,
+        aliases: &[],
+        value: Some("TEMPLATE"),
+        env: None,
+        help: &[
+            "Subdirectories of --dest to move files into, with the",
+            "placeholders of --template except {counter}, for example",
+            "{date:%Y}/{date:%Y-%m}/{date:%Y-%m-%d}. Missing directories",
+            "are created, directories that come out empty are left out.",
+        ],
+    },
+    OptionSpec {
+        long: "utc",
+        short: None,
+        aliases: &["-utc"],
+        value: None,
+        env: None,
+        help: &[
+            "Do not reinterpret MP4 timestamps of unrecognized cameras",
+            "into local time zone. Even though specification suggests",
+            "to use UTC for CreationDate and ModificationDate, some",
+            "cameras (DJI, GoPro) are saving it in a local time zone.",
+            "The writer of every MP4 file is recognized by its ftyp",
+            "brands, udta atoms, hdlr names and make tag or Apple make",
+            "key, known vendors use their own rule regardless of this",
+            "option:",
+            "apple, samsung, google - UTC; dji, gopro - local.",
+            "The rule used is shown next to every rename.",
+        ],
+    },
+    OptionSpec {
+        long: "mp4-zone",
+        short: None,
+        aliases: &["-mp4zone"],
+        value: Some("VENDOR=CLOCK"),
+        env: None,
+        help: &[
+            "Override the rule for MP4 files of VENDOR, CLOCK is 'utc'",
+            "or 'local'. VENDOR is one of the known vendors, sony, canon,",
+            "or the lowercase make tag of the file, for example",
+            "--mp4-zone sony=local. Can be given multiple times.",
+        ],
+    },
+    OptionSpec {
+        long: "jobs",
+        short: Some('j'),
+        aliases: &["-jobs"],
+        value: Some("N"),
+        env: Some("TIMESTAMPNAME_JOBS"),
+        help: &[
+            "Extract metadata from N files concurrently (default: 1).",
+            "Use 0 to run one worker per available CPU.",
+            "Output order and results do not depend on the number of workers.",
+        ],
+    },
+    OptionSpec {
+        long: "date-priority",
+        short: None,
+        aliases: &["-datepriority"],
+        value: Some("LIST"),
+        env: Some("TIMESTAMPNAME_DATE_PRIORITY"),
+        help: &[
+            "Which EXIF date tag to use, first one found in the file wins:",
+            "original  - DateTimeOriginal, when the photo was taken,",
+            "digitized - DateTimeDigitized, when the photo was scanned,",
+            "modified  - DateTime, when the file was last changed.",
+            "Default is original,digitized,modified. Tags not listed are",
+            "never used. Use 'earliest' to take the earliest of all tags.",
+            "The tag used is shown next to every rename.",
+        ],
+    },
+    OptionSpec {
+        long: "tz",
+        short: None,
+        aliases: &["-tz"],
+        value: Some("ZONE"),
+        env: Some("TIMESTAMPNAME_TZ"),
+        help: &[
+            "Convert all timestamps with known time zone into ZONE,",
+            "which is 'local', 'utc' or an offset like +02:00.",
+            "Photo time zone is taken from EXIF OffsetTime tags,",
+            "MP4 timestamps are in UTC unless --utc is used.",
+            "Files without time zone information are left as is.",
+        ],
+    },
+    OptionSpec {
+        long: "gps",
+        short: None,
+        aliases: &["-gps"],
+        value: None,
+        env: None,
+        help: &[
+            "Use EXIF GPS date and time instead of the camera clock for",
+            "photos that have it. GPS time is UTC and is converted into",
+            "the local time zone (or the one given with --tz).",
+        ],
+    },
+    OptionSpec {
+        long: "clock-offsets",
+        short: None,
+        aliases: &["-clockoffsets"],
+        value: None,
+        env: None,
+        help: &[
+            "Only show how far off the clock of each camera is, compared",
+            "to GPS time of the photos having both, and exit without",
+            "renaming. Cameras are told apart by EXIF Make and Model.",
+        ],
+    },
+    OptionSpec {
+        long: "cam-offset",
+        short: None,
+        aliases: &["-camoffset"],
+        value: Some("CAMERA=OFFSET"),
+        env: None,
+        help: &[
+            "Correct the clock of CAMERA by adding OFFSET to the EXIF dates",
+            "of its photos, for example 'Canon EOS R5=+01:00:17' or",
+            "'NIKON D750=-00:02'. CAMERA is the body serial number or",
+            "the make and model as shown by --clock-offsets, serial numbers",
+            "win over names. OFFSET is at most 36525 days. Can be given",
+            "multiple times.",
+        ],
+    },
+    OptionSpec {
+        long: "derive-offset",
+        short: None,
+        aliases: &["-deriveoffset"],
+        value: Some("REFERENCE,OTHER"),
+        env: None,
+        help: &[
+            "Only show the --cam-offset correction that makes the camera of",
+            "OTHER agree with the camera of REFERENCE, given two photos of",
+            "the same moment, and exit without renaming. Can be given",
+            "multiple times, the median of all pairs is shown per camera.",
+        ],
+    },
+    OptionSpec {
+        long: "shift",
+        short: None,
+        aliases: &["-shift"],
+        value: Some("DURATION"),
+        env: None,
+        help: &[
+            "Shift all timestamps by DURATION, for example -1h30m, +2d",
+            "or 90s (units: d, h, m, s), of at most 36525 days. The shift",
+            "is elapsed time, dates roll over and DST changes of the local",
+            "time zone are taken into account. Applied after --cam-offset",
+            "and --gps.",
+        ],
+    },
+    OptionSpec {
+        long: "from-name",
+        short: None,
+        aliases: &["-fromname"],
+        value: None,
+        env: None,
+        help: &[
+            "Take timestamps from file names of supported files that have",
+            "no timestamp in their metadata, instead of failing them.",
+            "Same as --fallback filename, tried before the other fallbacks",
+            "unless --fallback lists filename elsewhere.",
+            "Known names: Android (20180430_184327.jpg), Pixel",
+            "(PXL_20230102_101112345.jpg, UTC converted into the local",
+            "time zone), WhatsApp (IMG-20180430-WA0001.jpg, date only) and",
+            "screenshots. Such timestamps are marked as low confidence in",
+            "the listing.",
+        ],
+    },
+    OptionSpec {
+        long: "name-pattern",
+        short: None,
+        aliases: &["-namepattern"],
+        value: Some("REGEX"),
+        env: None,
+        help: &[
+            "Additional file name pattern for --from-name (implies it), tried",
+            "before the known ones. Named groups year, month and day are",
+            "required, hour, minute, second and millis are optional, e.g.",
+            r"'^DSC_(?P<year>\d{4})(?P<month>\d{2})(?P<day>\d{2})'.",
+            "Can be given multiple times.",
+        ],
+    },
+    OptionSpec {
+        long: "fallback",
+        short: None,
+        aliases: &["-fallback"],
+        value: Some("LIST"),
+        env: Some("TIMESTAMPNAME_FALLBACK"),
+        help: &[
+            "Where to take timestamps from for supported files that have",
+            "none in their metadata, tried in the listed order:",
+            "filename  - see --from-name,",
+            "mtime     - file modification time,",
+            "birthtime - file creation time, if the file system has it.",
+            "For example: --fallback filename,mtime. Files with broken",
+            "structure still fail. The source used is shown next to",
+            "every rename.",
+        ],
+    },
+    OptionSpec {
+        long: "unsupported",
+        short: None,
+        aliases: &["-unsupported"],
+        value: Some("POLICY"),
+        env: Some("TIMESTAMPNAME_UNSUPPORTED"),
+        help: &[
+            "What to do with files of unsupported types, like .heic:",
+            "skip    - leave them untouched (default),",
+            "report  - leave them untouched and list them,",
+            "fail    - list them and rename nothing,",
+            "include - rename them using --fallback timestamps, files",
+            "          without one are left untouched.",
+            "Skipped files are counted per extension in any case.",
+        ],
+    },
+    OptionSpec {
+        long: "recursive",
+        short: Some('r'),
+        aliases: &["-recursive"],
+        value: None,
+        env: None,
+        help: &[
+            "Also rename files of all subdirectories. Symbolic links to",
+            "directories are followed, every directory is visited once.",
+        ],
+    },
+    OptionSpec {
+        long: "numbering",
+        short: None,
+        aliases: &["-numbering"],
+        value: Some("MODE"),
+        env: None,
+        help: &[
+            "How files are numbered with --recursive or --dest:",
+            "directory - renamed in place, prefix counter per directory",
+            "            (default),",
+            "global    - one sequence for all files, which are moved",
+            "            into the current directory, the directory",
+            "            given as PATH, or --dest.",
+        ],
+    },
+    OptionSpec {
+        long: "hidden",
+        short: None,
+        aliases: &["-hidden"],
+        value: None,
+        env: None,
+        help: &[
+            "Include files and directories with names starting with a dot,",
+            "which are left out by default.",
+        ],
+    },
+    OptionSpec {
+        long: "exclude",
+        short: Some('x'),
+        aliases: &["-exclude"],
+        value: Some("GLOB"),
+        env: None,
+        help: &[
+            "Leave out files and directories matching GLOB, for example",
+            "'*.xmp' or 'DCIM/.thumbnails'. Patterns without a slash match",
+            "names at any depth, patterns with one match paths relative to",
+            "the renamed directory. '*' does not cross slashes, '**' does,",
+            "'?' matches a single character. Can be given multiple times.",
+        ],
+    },
+    OptionSpec {
+        long: "files-from",
+        short: None,
+        aliases: &["-filesfrom"],
+        value: Some("FILE"),
+        env: None,
+        help: &[
+            "Also rename the files and directories listed in FILE, one per",
+            "line or separated by NUL characters (as printed by",
+            "find -print0). Use - to read the list from standard input.",
+            "Every file is renamed once, even if given multiple times.",
+        ],
+    },
+    OptionSpec {
+        long: "keep-going",
+        short: Some('k'),
+        aliases: &["-keepgoing"],
+        value: None,
+        env: None,
+        help: &[
+            "Do not abort on the first file that fails to be processed,",
+            "rename all other files and list the failed ones at the end.",
+        ],
+    },
+];
+
+// environment variable of the option being read, errors about its value name it:
+static OPTION_VARIABLE: Mutex<Option<&'static str>> = Mutex::new(None);
+
+fn exit_with_error(message: String) -> ! {
+    eprintln!("{}", message);
+    if let Some(variable) = *OPTION_VARIABLE.lock().unwrap() {
+        eprintln!("The value is from environment variable {}.", variable);
+    }
+    eprintln!("Use --help to list the options.");
+    process::exit(EXIT_FAILURE);
+}
+
 fn main() {
     let mut dry_run: bool = false;
     let mut no_prefix: bool = false;
+    let mut millis: bool = false;
     let mut debug_output: bool = false;
     let mut utc: bool = false;
-    for arg in env::args().skip(1) {
-        match arg.as_ref() {
-            "-dry" => {
+    let mut mp4_clock_rules: Vec<(String, timestampname::timezone::Mp4Clock)> = Vec::new();
+    let mut date_priority = timestampname::DatePriority::default();
+    let mut jobs: usize = 1;
+    let mut keep_going: bool = false;
+    let mut target_zone: Option<timestampname::timezone::TargetZone> = None;
+    let mut gps_time: bool = false;
+    let mut clock_offsets: bool = false;
+    let mut camera_offsets: Vec<timestampname::clock::CameraOffset> = Vec::new();
+    let mut offset_pairs: Vec<(String, String)> = Vec::new();
+    let mut shift: Option<chrono::Duration> = None;
+    let mut fallbacks: Vec<timestampname::Fallback> = Vec::new();
+    let mut name_fallback: bool = false;
+    let mut unsupported_policy = timestampname::UnsupportedPolicy::Skip;
+    let mut name_patterns: Vec<timestampname::filename::FileNamePattern> = Vec::new();
+    let mut recursive: bool = false;
+    let mut include_hidden: bool = false;
+    let mut exclusions: Vec<String> = Vec::new();
+    let mut numbering = timestampname::Numbering::PerDirectory;
+    let mut files_from: Option<String> = None;
+    let mut name_template: Option<timestampname::template::NameTemplate> = None;
+    let mut destination: Option<PathBuf> = None;
+    let mut directory_template: Option<timestampname::template::DirectoryTemplate> = None;
+    let mut transfer = timestampname::Transfer::Move;
+    let mut delete_source: bool = false;
+    let mut prune: bool = false;
+
+    let parsed = arguments::parse(OPTIONS, env::args().skip(1)).unwrap_or_else(|e| exit_with_error(e));
+    for (name, value, variable) in parsed.options {
+        *OPTION_VARIABLE.lock().unwrap() = variable;
+        // only flags have no value:
+        let value: String = value.unwrap_or_default();
+        match name {
+            "help" => {
+                print_help_and_exit();
+            },
+            "version" => {
+                println!("TimestampNameRust {}", env!("CARGO_PKG_VERSION"));
+                process::exit(0);
+            },
+            "dry-run" => {
                 dry_run = true;
             },
-            "-debug" => {
+            "debug" => {
                 debug_output = true;
             },
-            "-noprefix" => {
+            "no-prefix" => {
                 no_prefix = true;
             },
-            "-utc" => {
+            "millis" => {
+                millis = true;
+            },
+            "utc" => {
                 utc = true;
             },
-            "-h" => {
-                print_help_and_exit();
+            "dest" => {
+                if value.is_empty() {
+                    exit_with_error("--dest requires a directory".to_string());
+                }
+                destination = Some(PathBuf::from(value));
             },
-            _ => {
-                eprintln!("Unrecognized argument: {}", arg);
-                process::exit(1);
-            }
+            "copy" => {
+                transfer = timestampname::Transfer::Copy;
+            },
+            "delete-source" => {
+                delete_source = true;
+            },
+            "link" => {
+                transfer = match value.as_str() {
+                    "hard" => timestampname::Transfer::HardLink,
+                    "symbolic" => timestampname::Transfer::SymbolicLink,
+                    _ => exit_with_error("--link requires one of: hard, symbolic".to_string())
+                };
+            },
+            "prune" => {
+                prune = true;
+            },
+            "dir-template" => {
+                directory_template = Some(timestampname::template::parse_directory_template(&value)
+                    .unwrap_or_else(|e| exit_with_error(format!("--dir-template {}: {}", value, e))));
+            },
+            "template" => {
+                name_template = Some(timestampname::template::parse_name_template(&value)
+                    .unwrap_or_else(|e| exit_with_error(format!("--template {}: {}", value, e))));
+            },
+            "mp4-zone" => {
+                mp4_clock_rules.push(timestampname::timezone::parse_mp4_clock_rule(&value)
+                    .unwrap_or_else(|| exit_with_error(
+                        "--mp4-zone requires a vendor and a clock like dji=local or samsung=utc".to_string())));
+            },
+            "keep-going" => {
+                keep_going = true;
+            },
+            "gps" => {
+                gps_time = true;
+            },
+            "clock-offsets" => {
+                clock_offsets = true;
+            },
+            "jobs" => {
+                jobs = value.parse::<usize>()
+                    .unwrap_or_else(|_| exit_with_error(
+                        "--jobs requires a non-negative number of workers".to_string()));
+            },
+            "date-priority" => {
+                date_priority = timestampname::DatePriority::parse(&value)
+                    .unwrap_or_else(|| exit_with_error(
+                        "--date-priority requires 'earliest' or a list like original,digitized,modified".to_string()));
+            },
+            "tz" => {
+                target_zone = Some(timestampname::timezone::parse_target_zone(&value)
+                    .unwrap_or_else(|| exit_with_error(
+                        "--tz requires a time zone: local, utc or offset like +02:00".to_string())));
+            },
+            "cam-offset" => {
+                camera_offsets.push(timestampname::clock::parse_camera_offset(&value)
+                    .unwrap_or_else(|| exit_with_error(
+                        format!("--cam-offset requires a camera and an offset like 'Canon EOS R5=+01:00:17', of at most {} days",
+                                timestampname::clock::MAX_SHIFT_DAYS))));
+            },
+            "derive-offset" => {
+                offset_pairs.push(value.split_once(',')
+                    .map(|(r, o)| (r.to_string(), o.to_string()))
+                    .unwrap_or_else(|| exit_with_error(
+                        "--derive-offset requires two file names like REFERENCE.jpg,OTHER.jpg".to_string())));
+            },
+            "shift" => {
+                shift = Some(timestampname::clock::parse_shift(&value)
+                    .unwrap_or_else(|| exit_with_error(
+                        format!("--shift requires a duration like -1h30m, +2d or 90s, of at most {} days",
+                                timestampname::clock::MAX_SHIFT_DAYS))));
+            },
+            "from-name" => {
+                name_fallback = true;
+            },
+            "fallback" => {
+                fallbacks = timestampname::parse_fallbacks(&value)
+                    .unwrap_or_else(|| exit_with_error(
+                        "--fallback requires a list like filename,mtime or birthtime".to_string()));
+            },
+            "name-pattern" => {
+                name_fallback = true;
+                name_patterns.push(timestampname::filename::parse_file_name_pattern(&value)
+                    .unwrap_or_else(|e| exit_with_error(format!("--name-pattern {}: {}", value, e))));
+            },
+            "unsupported" => {
+                unsupported_policy = timestampname::UnsupportedPolicy::parse(&value)
+                    .unwrap_or_else(|| exit_with_error(
+                        "--unsupported requires one of: skip, report, fail, include".to_string()));
+            },
+            "recursive" => {
+                recursive = true;
+            },
+            "hidden" => {
+                include_hidden = true;
+            },
+            "exclude" => {
+                if value.is_empty() {
+                    exit_with_error("--exclude requires a pattern like '*.xmp' or 'DCIM/.thumbnails'".to_string());
+                }
+                exclusions.push(value);
+            },
+            "numbering" => {
+                numbering = timestampname::Numbering::parse(&value)
+                    .unwrap_or_else(|| exit_with_error(
+                        "--numbering requires one of: directory, global".to_string()));
+            },
+            "files-from" => {
+                if value.is_empty() {
+                    exit_with_error(
+                        "--files-from requires a file with a list of paths, or - for standard input".to_string());
+                }
+                files_from = Some(value);
+            },
+            _ => panic!("option declared but not handled: {}", name)
         }
     }
+    // combinations of options are checked below, regardless of where the values came from:
+    *OPTION_VARIABLE.lock().unwrap() = None;
+    let paths: Vec<PathBuf> = parsed.positional.into_iter().map(PathBuf::from).collect();
+
+    let name_template = match name_template {
+        Some(_) if no_prefix || millis => {
+            exit_with_error("--template cannot be combined with --no-prefix or --millis".to_string())
+        }
+        Some(template) => template,
+        None => timestampname::template::default_name_template(no_prefix, millis)
+    };
+
+    // --fallback, given or from the environment, does not drop the file name fallback of
+    // --from-name and --name-pattern, file names are more specific than file times:
+    if name_fallback && !fallbacks.contains(&timestampname::Fallback::FileName) {
+        fallbacks.insert(0, timestampname::Fallback::FileName);
+    }
+    if unsupported_policy == timestampname::UnsupportedPolicy::Include && fallbacks.is_empty() {
+        exit_with_error("--unsupported include requires --fallback or --from-name".to_string());
+    }
+
+    if directory_template.is_some() && destination.is_none() {
+        exit_with_error("--dir-template requires --dest".to_string());
+    }
+
+    if transfer != timestampname::Transfer::Move && destination.is_none() {
+        exit_with_error("--copy and --link require --dest".to_string());
+    }
+    if prune && !matches!(transfer, timestampname::Transfer::HardLink | timestampname::Transfer::SymbolicLink) {
+        exit_with_error("--prune requires --link".to_string());
+    }
+    if delete_source && transfer != timestampname::Transfer::Copy {
+        exit_with_error("--delete-source requires --copy".to_string());
+    }
+
+    if numbering == timestampname::Numbering::Global && !recursive && destination.is_none() {
+        exit_with_error("--numbering global requires --recursive or --dest".to_string());
+    }
+    // without destination files are moved into the top directory, which has to be unambiguous:
+    if numbering == timestampname::Numbering::Global && destination.is_none()
+        && (paths.len() > 1 || files_from.is_some() || paths.iter().any(|p| !p.is_dir())) {
+        exit_with_error("--numbering global requires a single directory, not files or --files-from".to_string());
+    }
 
     let cmd_args = timestampname::CommandLineArguments {
         dry_run,
-        no_prefix,
+        name_template,
         debug_output,
-        utc
+        utc,
+        mp4_clock_rules,
+        date_priority,
+        jobs,
+        keep_going,
+        target_zone,
+        gps_time,
+        clock_offsets,
+        camera_offsets,
+        offset_pairs,
+        shift,
+        fallbacks,
+        name_patterns,
+        unsupported_policy,
+        recursive,
+        include_hidden,
+        exclusions,
+        numbering,
+        paths,
+        files_from,
+        destination,
+        directory_template,
+        transfer,
+        delete_source,
+        prune
     };
 
     match env::current_dir()
         .map_err(|e|
-            timestampname::failures::Failure::env_failure_caused(
+            Failure::env_io(
                 "Get current working directory".to_string(), e))
         .and_then(|path| timestampname::execute(path, cmd_args)) {
         Err(e) => {
             eprintln!("Failure:\n{}\n", e);
-            process::exit(1);
+            if let Some(hint) = failure_hint(&e) {
+                eprintln!("{}", hint);
+            }
+            process::exit(EXIT_FAILURE);
         },
-        Ok(_) => {}
+        Ok(timestampname::Outcome::TotalFailure) => process::exit(EXIT_FAILURE),
+        Ok(timestampname::Outcome::PartialSuccess) => process::exit(EXIT_PARTIAL_SUCCESS),
+        Ok(timestampname::Outcome::Success) => {}
     }
 }
 
+fn failure_hint(failure: &Failure) -> Option<&'static str> {
+    return match failure.kind() {
+        FailureKind::Truncated { .. } | FailureKind::Malformed { .. } | FailureKind::MissingTimestamp =>
+            Some("Use --keep-going to rename all other files and leave this one untouched."),
+        FailureKind::Conflict =>
+            Some("Nothing was renamed, resolve the conflicting names and try again."),
+        FailureKind::Io | FailureKind::Unsupported => None,
+    };
+}
+
 fn print_help_and_exit() {
     println!("
-Usage: TimestampNameRust [ options ]
+Usage: TimestampNameRust [ options ] [ PATH... ]
+
+Renames supported files of the current directory, or of the given
+directories, and the given files. Options can be combined like -nr,
+values given as --jobs 4, --jobs=4 or -j4, arguments after -- are
+taken as paths.
 
 Options:
-    -h          Display help and exit.
-    -dry        Only show the operations but do not perform a rename.
-    -debug      Enable debug output.
-    -noprefix   Do not add numerical prefix to the renamed files
-                (works if not more than one file is shot per second).
-    -utc        Do not reinterpret MP4 timestamps into local time zone.
-                Even though specification suggests to use UTC for CreationDate
-                and ModificationDate, some cameras (DJI?) are saving it
-                in a local time zone, so the time zone offset will double
-                if we will apply conversion to local time zone on top of it.
-                This option will produce incorrectly named files if a folder
-                contains video files from DJI and Samsung for example.");
+{}
+Exit codes:
+    0           All supported files were renamed.
+    1           Aborted due to a failure. Files listed before the failure
+                may have been renamed already.
+    2           Some files were renamed, but some failed (only with --keep-going).",
+             arguments::format_help(OPTIONS));
     process::exit(0);
 }
@@ -0,0 +1,302 @@
+use chrono::Duration;
+use chrono::Local;
+use chrono::LocalResult;
+use chrono::NaiveDateTime;
+use chrono::Offset;
+use chrono::TimeZone;
+
+use super::FileMetadata;
+use super::TimestampSource;
+use super::timestamp::Timestamp;
+
+pub struct CameraOffset {
+    /// Camera name as shown by `camera_name` or the body serial number.
+    pub camera: String,
+    /// Correction added to the camera clock.
+    pub offset: Duration,
+}
+
+/// Longest shift or clock correction, in days, about a hundred years. Longer ones are mistakes
+/// and would take timestamps out of the supported range.
+pub const MAX_SHIFT_DAYS: i64 = 36525;
+
+fn bounded_seconds(seconds: i64) -> Option<Duration> {
+    if seconds.abs() > MAX_SHIFT_DAYS * 86400 {
+        return None;
+    }
+    return Duration::try_seconds(seconds);
+}
+
+/// Parses offsets as `+HH:MM:SS` or `+HH:MM`, hours are not limited to a day
+/// but the offset is to [`MAX_SHIFT_DAYS`].
+pub fn parse_clock_offset(value: &str) -> Option<Duration> {
+    let sign: i64 = match value.chars().next()? {
+        '+' => 1,
+        '-' => -1,
+        _ => return None
+    };
+    let parts: Vec<&str> = value[1..].split(':').collect();
+    if parts.len() < 2 || parts.len() > 3
+        || parts.iter().any(|p| p.len() < 2 || !p.bytes().all(|b| b.is_ascii_digit())) {
+        return None;
+    }
+    let hours: i64 = parts[0].parse().ok()?;
+    let minutes: i64 = parts[1].parse().ok()?;
+    let seconds: i64 = match parts.get(2) {
+        Some(s) => s.parse().ok()?,
+        None => 0
+    };
+    if minutes > 59 || seconds > 59 || parts[1].len() != 2 || parts.get(2).is_some_and(|s| s.len() != 2) {
+        return None;
+    }
+    return bounded_seconds(sign * hours.checked_mul(3600)?.checked_add(minutes * 60 + seconds)?);
+}
+
+/// Parses shifts like `-1h30m`, `+2d` or `90s`: a sign followed by
+/// numbers with `d`, `h`, `m` or `s` units, each unit at most once, up to [`MAX_SHIFT_DAYS`].
+pub fn parse_shift(value: &str) -> Option<Duration> {
+    let (sign, mut rest): (i64, &str) = match value.chars().next()? {
+        '+' => (1, &value[1..]),
+        '-' => (-1, &value[1..]),
+        _ => (1, value)
+    };
+    if rest.is_empty() {
+        return None;
+    }
+    let mut seen_units: Vec<char> = Vec::new();
+    let mut seconds: i64 = 0;
+    while !rest.is_empty() {
+        let digits = rest.bytes().take_while(|b| b.is_ascii_digit()).count();
+        let unit: char = rest[digits..].chars().next()?;
+        if digits == 0 || seen_units.contains(&unit) {
+            return None;
+        }
+        let amount: i64 = rest[..digits].parse().ok()?;
+        let unit_seconds: i64 = match unit {
+            'd' => 86400,
+            'h' => 3600,
+            'm' => 60,
+            's' => 1,
+            _ => return None
+        };
+        seconds = seconds.checked_add(amount.checked_mul(unit_seconds)?)?;
+        seen_units.push(unit);
+        rest = &rest[digits + 1..];
+    }
+    return bounded_seconds(sign * seconds);
+}
+
+/// Parses `CAMERA=OFFSET`, for example `Canon EOS R5=+01:00:17`.
+pub fn parse_camera_offset(value: &str) -> Option<CameraOffset> {
+    let (camera, offset) = value.rsplit_once('=')?;
+    if camera.trim().is_empty() {
+        return None;
+    }
+    return Some(CameraOffset {
+        camera: camera.trim().to_string(),
+        offset: parse_clock_offset(offset.trim())?,
+    });
+}
+
+/// Name identifying the camera a file was shot with, models usually repeat the make.
+pub fn camera_name(md: &FileMetadata) -> String {
+    return match (&md.camera_make, &md.camera_model) {
+        (Some(make), Some(model)) if model.to_lowercase().starts_with(&make.to_lowercase()) => model.to_string(),
+        (Some(make), Some(model)) => format!("{} {}", make, model),
+        (Some(make), None) => make.to_string(),
+        (None, Some(model)) => model.to_string(),
+        (None, None) => "unknown camera".to_string()
+    };
+}
+
+fn matches_camera_name(md: &FileMetadata, camera: &str) -> bool {
+    if md.camera_make.is_none() && md.camera_model.is_none() {
+        return false;
+    }
+    return camera_name(md).eq_ignore_ascii_case(camera);
+}
+
+/// Corrects camera clock timestamps by the offset configured for the camera,
+/// serial number matches are preferred over names. Returns the number of corrected files.
+pub fn apply_camera_offsets(items: &mut [FileMetadata], offsets: &[CameraOffset]) -> usize {
+    let mut corrected: usize = 0;
+    for item in items.iter_mut() {
+        // only EXIF dates come from the camera clock:
+        if !matches!(item.timestamp.source, TimestampSource::Exif(_)) {
+            continue;
+        }
+        let by_serial = offsets.iter().find(|o| item.camera_serial.as_deref() == Some(o.camera.as_str()));
+        let offset = match by_serial.or_else(|| offsets.iter().find(|o| matches_camera_name(item, &o.camera))) {
+            Some(o) => o.offset,
+            None => continue
+        };
+        item.timestamp.local += offset;
+        corrected += 1;
+    }
+    return corrected;
+}
+
+/// Key to configure the offset of the camera with, serial number if known.
+fn camera_key(md: &FileMetadata) -> String {
+    return match &md.camera_serial {
+        Some(serial) => serial.to_string(),
+        None => camera_name(md)
+    };
+}
+
+/// Prints the corrections making other cameras agree with the reference camera,
+/// derived from pairs of images of the same moment. Wall clock times are compared,
+/// so a camera left on another time zone gets the zone difference included.
+/// Returns false if none of the pairs could be used.
+pub fn print_derived_offsets(items: &[FileMetadata], pairs: &[(String, String)]) -> bool {
+    // reference key, camera key and corrections:
+    let mut derived: Vec<(String, String, Vec<Duration>)> = Vec::new();
+    for (reference_name, other_name) in pairs {
+        let reference = items.iter().find(|md| &md.file_name == reference_name);
+        let other = items.iter().find(|md| &md.file_name == other_name);
+        let (reference, other) = match (reference, other) {
+            (Some(r), Some(o)) => (r, o),
+            _ => {
+                println!("Skipping pair {} and {}: both must be supported files of this directory.",
+                         reference_name, other_name);
+                continue;
+            }
+        };
+        let correction = reference.timestamp.local - other.timestamp.local;
+        let (reference_key, other_key) = (camera_key(reference), camera_key(other));
+        match derived.iter_mut().find(|d| d.0 == reference_key && d.1 == other_key) {
+            Some(d) => d.2.push(correction),
+            None => derived.push((reference_key, other_key, vec![correction]))
+        }
+    }
+    if derived.is_empty() {
+        return false;
+    }
+    println!("Derived camera clock corrections:");
+    for (reference_key, other_key, corrections) in derived.iter_mut() {
+        corrections.sort();
+        println!("    --cam-offset '{}={}'    (median of {} pairs, relative to {})",
+                 other_key,
+                 format_clock_offset(corrections[corrections.len() / 2]),
+                 corrections.len(),
+                 reference_key);
+    }
+    return true;
+}
+
+/// Difference between the camera clock and GPS time, positive if the camera is ahead.
+/// Without time zone information the camera clock is taken as UTC,
+/// so the difference includes the zone offset the camera was set to.
+fn clock_offset(md: &FileMetadata) -> Option<Duration> {
+    let gps = md.gps_timestamp?;
+    let camera_utc: NaiveDateTime = match md.timestamp.instant() {
+        Some(instant) => instant.naive_utc(),
+        None => md.timestamp.local
+    };
+    return Some(camera_utc - gps.naive_utc());
+}
+
+/// Formats a clock offset as `+HH:MM:SS`, rounded to whole seconds.
+pub fn format_clock_offset(offset: Duration) -> String {
+    let millis = offset.num_milliseconds();
+    let sign = if millis < 0 { '-' } else { '+' };
+    let seconds = (millis.abs() + 500) / 1000;
+    return format!("{}{:02}:{:02}:{:02}", sign, seconds / 3600, seconds / 60 % 60, seconds % 60);
+}
+
+/// Prints the clock offset of every camera, calculated from files having both
+/// a camera timestamp and GPS time.
+pub fn print_clock_offsets(items: &[FileMetadata]) {
+    // camera name, offsets and whether any of the files had no time zone information:
+    let mut cameras: Vec<(String, Vec<Duration>, bool)> = Vec::new();
+    let mut without_gps: usize = 0;
+    for item in items {
+        let offset = match item.timestamp.source {
+            TimestampSource::Exif(_) => clock_offset(item),
+            _ => None
+        };
+        let offset = match offset {
+            Some(o) => o,
+            None => {
+                without_gps += 1;
+                continue;
+            }
+        };
+        let name = camera_name(item);
+        let position = match cameras.iter().position(|c| c.0 == name) {
+            Some(p) => p,
+            None => {
+                cameras.push((name, Vec::new(), false));
+                cameras.len() - 1
+            }
+        };
+        cameras[position].1.push(offset);
+        cameras[position].2 |= item.timestamp.offset.is_none();
+    }
+
+    if cameras.is_empty() {
+        println!("No files with both camera and GPS time found.");
+        return;
+    }
+    cameras.sort_by(|c1, c2| c1.0.cmp(&c2.0));
+    let width: usize = cameras.iter().map(|c| c.0.len()).max().unwrap_or(0);
+    println!("Camera clock offsets relative to GPS time:");
+    for (name, offsets, zoneless) in cameras.iter_mut() {
+        offsets.sort();
+        println!("    {:width$}    {:>5} files    median {}    range {} .. {}{}",
+                 name,
+                 offsets.len(),
+                 format_clock_offset(offsets[offsets.len() / 2]),
+                 format_clock_offset(offsets[0]),
+                 format_clock_offset(offsets[offsets.len() - 1]),
+                 if *zoneless { "    (includes time zone offset)" } else { "" },
+                 width = width);
+    }
+    if without_gps > 0 {
+        println!("{} files without GPS time were not taken into account.", without_gps);
+    }
+}
+
+/// Shifts every timestamp by the elapsed time, in the time zone of the timestamp:
+/// its own offset if known, local time zone otherwise. Timestamps in local time zone
+/// get the offset valid after the shift, so crossing a DST boundary keeps wall clocks right.
+pub fn shift_timestamps(items: &mut [FileMetadata], shift: Duration) {
+    for item in items.iter_mut() {
+        let source = item.timestamp.source;
+        item.timestamp = match item.timestamp.instant() {
+            Some(instant) => {
+                let shifted = instant + shift;
+                // offsets of local time zone follow its DST rules:
+                match Local.offset_from_utc_datetime(&instant.naive_utc()).fix() == *instant.offset() {
+                    true => Timestamp::zoned(shifted.with_timezone(&Local).fixed_offset(), source),
+                    false => Timestamp::zoned(shifted, source)
+                }
+            }
+            None => {
+                let naive = item.timestamp.local;
+                let shifted = match Local.from_local_datetime(&naive) {
+                    // the earlier of the repeated hour, as a camera clock would show it first:
+                    LocalResult::Single(t) | LocalResult::Ambiguous(t, _) => (t + shift).naive_local(),
+                    // wall clock time skipped by DST does not exist, nothing better than plain arithmetic:
+                    LocalResult::None => naive + shift
+                };
+                Timestamp::create(shifted, None, source)
+            }
+        };
+    }
+}
+
+/// Replaces camera timestamps with GPS time converted into the local time zone,
+/// returns the number of files that had GPS time.
+pub fn use_gps_timestamps(items: &mut [FileMetadata]) -> usize {
+    let mut replaced: usize = 0;
+    for item in items.iter_mut() {
+        let gps = match item.gps_timestamp {
+            Some(g) => g,
+            None => continue
+        };
+        item.timestamp = Timestamp::zoned(gps.with_timezone(&Local).fixed_offset(), TimestampSource::GpsTimeStamp);
+        replaced += 1;
+    }
+    return replaced;
+}
@@ -1,27 +1,187 @@
 use std::fs;
+use std::fs::File;
+use std::fs::OpenOptions;
+use std::io;
+use std::io::Read;
+use std::io::Write;
+use std::os::unix::fs::MetadataExt;
 use std::os::unix::fs::PermissionsExt;
+use std::os::unix::fs::symlink;
+#[cfg(target_os = "linux")]
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+
+use sha2::Digest;
+use sha2::Sha256;
 
 use super::RenameOperation;
+use super::Transfer;
 use super::failures::Failure;
 
-pub fn execute_operations(operations: &Vec<RenameOperation>, dry_run: bool) -> Result<(), Failure> {
+pub fn execute_operations(operations: &[RenameOperation],
+                          transfer: Transfer,
+                          delete_source: bool,
+                          dry_run: bool) -> Result<(), Failure> {
     for (i, o) in operations.iter().enumerate() {
-        print!("\rRenaming files: {}/{}...", i + 1, operations.len());
-        if !dry_run {
-            fs::rename(&o.from, &o.to)
-                .map_err(|e| Failure::file_failure_caused(
-                    o.from.to_string(),
-                    "Failed to rename".to_string(),
-                    e))?;
-            if cfg!(unix) {
+        match transfer {
+            Transfer::Move => print!("\rRenaming files: {}/{}...", i + 1, operations.len()),
+            Transfer::Copy => print!("\rCopying files: {}/{}...", i + 1, operations.len()),
+            Transfer::HardLink | Transfer::SymbolicLink => print!("\rLinking files: {}/{}...", i + 1, operations.len()),
+        }
+        // links of the previous run are kept:
+        if !dry_run && !already_linked(&o.from, &o.to, transfer) {
+            if let Some(folder) = Path::new(&o.to).parent().filter(|f| !f.as_os_str().is_empty()) {
+                fs::create_dir_all(folder)
+                    .map_err(|e| Failure::file_io(
+                        o.to.to_string(),
+                        "Failed to create destination folder".to_string(),
+                        e))?;
+            }
+            match transfer {
+                Transfer::Move => move_file(&o.from, &o.to)
+                    .map_err(|e| Failure::file_io(
+                        o.from.to_string(),
+                        "Failed to rename".to_string(),
+                        e))?,
+                Transfer::Copy => copy_file(&o.from, &o.to)
+                    .map_err(|e| Failure::file_io(
+                        o.from.to_string(),
+                        format!("Failed to copy to {}", o.to),
+                        e))?,
+                Transfer::HardLink => fs::hard_link(&o.from, &o.to)
+                    .map_err(|e| Failure::file_io(
+                        o.from.to_string(),
+                        format!("Failed to link to {}", o.to),
+                        e))?,
+                // absolute, so that links do not depend on where the view is:
+                Transfer::SymbolicLink => fs::canonicalize(&o.from)
+                    .and_then(|source| symlink(source, &o.to))
+                    .map_err(|e| Failure::file_io(
+                        o.from.to_string(),
+                        format!("Failed to link to {}", o.to),
+                        e))?,
+            }
+            // permissions of links are the ones of their sources, which are left untouched:
+            if cfg!(unix) && (transfer == Transfer::Move || transfer == Transfer::Copy) {
                 fs::set_permissions(&o.to, PermissionsExt::from_mode(0o444))
-                    .map_err(|e| Failure::file_failure_caused(
+                    .map_err(|e| Failure::file_io(
                         o.to.to_string(),
                         "Failed to set permissions".to_string(),
                         e))?;
             }
+            // only after the copy was verified:
+            if transfer == Transfer::Copy && delete_source {
+                fs::remove_file(&o.from)
+                    .map_err(|e| Failure::file_io(
+                        o.from.to_string(),
+                        "Failed to delete copied file".to_string(),
+                        e))?;
+            }
         }
     }
     println!(" done.");
     return Ok(());
 }
+
+/// Whether the target is a link to the source made by a previous run.
+pub fn already_linked(from: &str, to: &str, transfer: Transfer) -> bool {
+    return match transfer {
+        Transfer::HardLink => match (fs::symlink_metadata(from), fs::symlink_metadata(to)) {
+            (Ok(source), Ok(target)) => source.dev() == target.dev() && source.ino() == target.ino(),
+            _ => false
+        },
+        Transfer::SymbolicLink => match (fs::canonicalize(from), fs::read_link(to)) {
+            (Ok(source), Ok(target)) => source == target,
+            _ => false
+        },
+        Transfer::Move | Transfer::Copy => false,
+    };
+}
+
+/// Renames the file, or copies and removes it if the destination is on another file system.
+fn move_file(from: &str, to: &str) -> Result<(), io::Error> {
+    return match fs::rename(from, to) {
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+            copy_file(from, to)?;
+            fs::remove_file(from)
+        }
+        r => r
+    };
+}
+
+/// Copies the file with its modification time, never overwriting the destination,
+/// and compares the checksum of the source taken while copying with the one of the copy
+/// read back from disk. Copies that fail are removed.
+fn copy_file(from: &str, to: &str) -> Result<(), io::Error> {
+    let mut source = File::open(from)?;
+    // the destination could have appeared after verification:
+    let mut copy = OpenOptions::new().write(true).create_new(true).open(to)?;
+    let copied = copy_with_checksum(&mut source, &mut copy)
+        .and_then(|source_checksum| {
+            copy.set_modified(source.metadata()?.modified()?)?;
+            copy.sync_all()?;
+            drop_cached_pages(&copy);
+            return match checksum(&mut File::open(to)?)? == source_checksum {
+                true => Ok(()),
+                false => Err(io::Error::new(io::ErrorKind::InvalidData, "checksum of the copy does not match"))
+            };
+        });
+    if copied.is_err() {
+        let _ = fs::remove_file(to);
+    }
+    return copied;
+}
+
+// the source is read only once, its checksum is taken from the data being written,
+// or from the source alone if the copy shares its blocks:
+fn copy_with_checksum(source: &mut File, copy: &mut File) -> Result<Vec<u8>, io::Error> {
+    if clone_blocks(source, copy) {
+        return checksum(source);
+    }
+    let mut hasher = Sha256::new();
+    let mut buffer: Vec<u8> = vec![0; 1 << 20];
+    loop {
+        let read = match source.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e)
+        };
+        hasher.update(&buffer[..read]);
+        copy.write_all(&buffer[..read])?;
+    }
+    return Ok(hasher.finalize().to_vec());
+}
+
+fn checksum(file: &mut File) -> Result<Vec<u8>, io::Error> {
+    let mut hasher = Sha256::new();
+    io::copy(file, &mut hasher)?;
+    return Ok(hasher.finalize().to_vec());
+}
+
+/// Asks the kernel to drop the synced pages of the file from its cache, so that reading it back
+/// gets the data from the disk. This is advice, pages still mapped elsewhere can stay cached,
+/// and where it is not available the checksum covers only corruption before the page cache.
+#[cfg(target_os = "linux")]
+fn drop_cached_pages(file: &File) {
+    unsafe {
+        libc::posix_fadvise(file.as_raw_fd(), 0, 0, libc::POSIX_FADV_DONTNEED);
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn drop_cached_pages(_file: &File) {}
+
+/// Makes the copy share the blocks of the source on file systems that support it,
+/// like Btrfs and XFS, returns false if the data has to be copied.
+#[cfg(target_os = "linux")]
+fn clone_blocks(source: &File, copy: &File) -> bool {
+    unsafe {
+        return libc::ioctl(copy.as_raw_fd(), libc::FICLONE, source.as_raw_fd()) == 0;
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn clone_blocks(_source: &File, _copy: &File) -> bool {
+    return false;
+}
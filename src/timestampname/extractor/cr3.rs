@@ -1,76 +1,63 @@
 use super::input::Input;
+use super::ExtractorOptions;
 use super::FileMetadata;
 use super::Failure;
-use super::tiff::tiff_extract_metadata_creation_timestamp;
+use super::tiff::IfdKind;
+use super::tiff::TiffTags;
+use super::tiff::tiff_metadata_from_tags;
+use super::tiff::tiff_read_tags;
 
 // following resources were used to implement this parser:
 // https://github.com/lclevy/canon_cr3
 
-fn canon_box_uuid() -> Result<(u64, u64), Failure> {
-    let canon_uuid = "85c0b687820f11e08111f4ce462b6a48";
-    let msb = u64::from_str_radix(&canon_uuid[0..16], 16)
-        .map_err(|e| Failure::env_failure_caused(
-            "parsing Canon Box UUID MSB to u64".to_string(),
-            e))?;
-    let lsb = u64::from_str_radix(&canon_uuid[16..32], 16)
-        .map_err(|e| Failure::env_failure_caused(
-            "parsing Canon Box UUID LSB to u64".to_string(),
-            e))?;
-    return Ok((msb, lsb));
-}
+// Canon box UUID: 85c0b687-820f-11e0-8111-f4ce462b6a48
+const CANON_BOX_UUID: (u64, u64) = (0x85c0b687820f11e0, 0x8111f4ce462b6a48);
 
-fn extract_timestamp_from_tiff_box(box_name: &str, input: &mut Input) -> Result<Option<FileMetadata>, Failure> {
+fn read_tiff_box_tags(box_name: &str,
+                      root_ifd: IfdKind,
+                      input: &mut Input) -> Result<Option<TiffTags>, Failure> {
     let file_name = input.name().to_string();
-    let mut target_box = input.quicktime_search_box(box_name)
-        .map_err(|e| Failure::file_failure_caused(
+    input.seek(0)
+        .map_err(|e| Failure::file_io(
             file_name.to_string(),
-            format!("failed to find box: {}", box_name),
+            "failed to rewind till canon box start".to_string(),
             e))?;
-    return tiff_extract_metadata_creation_timestamp(&mut target_box);
+    return match input.quicktime_search_box(box_name)? {
+        Some(mut target_box) => tiff_read_tags(&mut target_box, root_ifd).map(Some),
+        None => Ok(None)
+    };
 }
 
-pub fn cr3_extract_metadata_creation_timestamp(input: &mut Input) -> Result<Option<FileMetadata>, Failure> {
+pub fn cr3_extract_metadata_creation_timestamp(input: &mut Input, options: &ExtractorOptions) -> Result<Option<FileMetadata>, Failure> {
     let file_name = input.name().to_string();
-    let mut moov_box = input.quicktime_search_box("moov")
-        .map_err(|e| Failure::file_failure_caused(
+    let mut moov_box = input.quicktime_search_box("moov")?
+        .ok_or_else(|| Failure::missing_timestamp(
             file_name.to_string(),
-            "moov box not found".to_string(),
-            e))?;
-    let mut canon_box = moov_box.quicktime_search_uuid_box(canon_box_uuid()?)
-        .map_err(|e| Failure::file_failure_caused(
-            file_name.to_string(),
-            "canon box not found".to_string(),
-            e))?;
-
-    let cmt1_timestamp = extract_timestamp_from_tiff_box("CMT1", &mut canon_box)?;
-
-    canon_box.seek(0)
-        .map_err(|e| Failure::file_failure_caused(
+            "moov box not found".to_string()))?;
+    let mut canon_box = moov_box.quicktime_search_uuid_box(CANON_BOX_UUID)?
+        .ok_or_else(|| Failure::missing_timestamp(
             file_name.to_string(),
-            "failed to rewind till canon box start".to_string(),
-            e))?;
-    let cmt2_timestamp = extract_timestamp_from_tiff_box("CMT2", &mut canon_box)?;
+            "canon box not found".to_string()))?;
 
-    match cmt1_timestamp {
-        None => {
-            match cmt2_timestamp {
-                None => return Err(Failure::file_failure(
-                    file_name.to_string(),
-                    "timestamps not found in CMT1 and CMT2 boxes".to_string())),
-                Some(md2) => return Ok(Some(md2))
-            }
-        }
-        Some(md1) => {
-            match cmt2_timestamp {
-                None => return Ok(Some(md1)),
-                Some(md2) => {
-                    if md1.creation_timestamp < md2.creation_timestamp {
-                        return Ok(Some(md1));
-                    } else {
-                        return Ok(Some(md2));
-                    }
-                }
-            }
+    // CMT1 holds IFD0 with DateTime, Make and Model, CMT2 holds EXIF IFD with the rest of date tags,
+    // tags of both are combined so date priority applies as for a single TIFF structure:
+    let mut tags = TiffTags::default();
+    let mut found = false;
+    for box_name in ["CMT1", "CMT2"] {
+        if let Some(box_tags) = read_tiff_box_tags(box_name, IfdKind::Tiff, &mut canon_box)? {
+            tags.merge(box_tags);
+            found = true;
         }
     }
+    if !found {
+        return Err(Failure::missing_timestamp(
+            file_name.to_string(),
+            "CMT1 and CMT2 boxes not found".to_string()));
+    }
+    // CMT4 holds GPS IFD, which is optional and should not fail the file:
+    if let Ok(Some(gps_tags)) = read_tiff_box_tags("CMT4", IfdKind::Gps, &mut canon_box) {
+        tags.merge(gps_tags);
+    }
+
+    return tiff_metadata_from_tags(input, &tags, options).map(Some);
 }
@@ -6,16 +6,20 @@ use std::io::SeekFrom;
 
 use super::Endianness;
 use super::inputbox::InputBox;
+use super::super::failures::TruncatedRead;
 
 pub struct Input<'f> {
     file: &'f InputBox,
+    // absolute offset of this input within the file:
     offset: u64,
+    // length of this input, never exceeds the parent input:
     limit: u64,
+    // position relative to the offset, never exceeds the limit:
     cursor: u64,
 }
 
 impl<'f> Input<'f> {
-    pub fn create(input_box: &InputBox) -> Input {
+    pub fn create(input_box: &InputBox) -> Input<'_> {
         return Input {
             file: input_box,
             offset: 0,
@@ -29,91 +33,197 @@ impl<'f> Input<'f> {
     pub fn ext(&self) -> &str {
         return self.file.ext();
     }
+    /// Absolute position of the cursor within the file.
+    pub fn position(&self) -> u64 {
+        return self.offset + self.cursor;
+    }
+    /// Number of bytes between the cursor and the end of this input.
+    pub fn remaining(&self) -> u64 {
+        return self.limit - self.cursor;
+    }
 
-    pub fn read_u16(&mut self, bo: &Endianness) -> io::Result<u16> {
-        // TODO overflow check
-        if self.cursor + 2 >= self.limit {
-            return Err(io::Error::new(
-                ErrorKind::UnexpectedEof,
-                format!("EOF: reading 2 bytes from {}, input length: {}", self.cursor, self.limit)));
+    fn out_of_bounds(&self, requested: u64) -> io::Error {
+        return io::Error::new(
+            ErrorKind::UnexpectedEof,
+            TruncatedRead {
+                offset: self.position(),
+                requested,
+                available: self.remaining(),
+            });
+    }
+
+    fn read_exact(&mut self, buf: &mut [u8]) -> io::Result<()> {
+        let len = buf.len() as u64;
+        // reading exactly till the end of input is fine, hence no >= here:
+        if len > self.remaining() {
+            return Err(self.out_of_bounds(len));
         }
+        // inputs of the same file share the file cursor, so every read starts
+        // from the position of this input, regardless of reads done through other inputs:
+        let mut file = self.file.file();
+        file.seek(SeekFrom::Start(self.position()))?;
+        file.read_exact(buf)
+            .map_err(|e| match e.kind() {
+                // file was truncated after its size was taken:
+                ErrorKind::UnexpectedEof => self.out_of_bounds(len),
+                _ => e
+            })?;
+        self.cursor += len;
+        return Ok(());
+    }
+
+    pub fn read_u16(&mut self, bo: &Endianness) -> io::Result<u16> {
         let mut buf: [u8; 2] = [0; 2];
-        self.file.file().read_exact(&mut buf)?;
-        self.cursor = self.cursor + 2;
+        self.read_exact(&mut buf)?;
         match bo {
             Endianness::Big => Ok(u16::from_be_bytes(buf)),
             Endianness::Little => Ok(u16::from_le_bytes(buf))
         }
     }
     pub fn read_u32(&mut self, bo: &Endianness) -> io::Result<u32> {
-        // TODO overflow check
-        if self.cursor + 4 >= self.limit {
-            return Err(io::Error::new(
-                ErrorKind::UnexpectedEof,
-                format!("EOF: reading 4 bytes from {}, input length: {}", self.cursor, self.limit)));
-        }
         let mut buf: [u8; 4] = [0; 4];
-        self.file.file().read_exact(&mut buf)?;
-        self.cursor = self.cursor + 4;
+        self.read_exact(&mut buf)?;
         match bo {
             Endianness::Big => Ok(u32::from_be_bytes(buf)),
             Endianness::Little => Ok(u32::from_le_bytes(buf))
         }
     }
     pub fn read_u64(&mut self, bo: &Endianness) -> io::Result<u64> {
-        // TODO overflow check
-        if self.cursor + 8 >= self.limit {
-            return Err(io::Error::new(
-                ErrorKind::UnexpectedEof,
-                format!("EOF: reading 8 bytes from {}, input length: {}", self.cursor, self.limit)));
-        }
         let mut buf: [u8; 8] = [0; 8];
-        self.file.file().read_exact(&mut buf)?;
-        self.cursor = self.cursor + 8;
+        self.read_exact(&mut buf)?;
         match bo {
             Endianness::Big => Ok(u64::from_be_bytes(buf)),
             Endianness::Little => Ok(u64::from_le_bytes(buf))
         }
     }
-    pub fn read_string(&mut self, len: u64) -> io::Result<String> {
-        // TODO overflow check
-        if self.cursor + len >= self.limit {
-            return Err(io::Error::new(
-                ErrorKind::UnexpectedEof,
-                format!("EOF: reading {} bytes from {}, input length: {}", len, self.cursor, self.limit)));
-        }
-        let mut take_input = self.file.file().take(len);
-        let mut buffer = String::new();
-        let read = take_input.read_to_string(&mut buffer)?;
-        if (read as u64) < len {
-            return Err(io::Error::new(
-                io::ErrorKind::Other,
-                "string read result is shorter than expected"));
+    pub fn read_bytes(&mut self, len: u64) -> io::Result<Vec<u8>> {
+        // checking before allocating, len may come from a corrupted field:
+        if len > self.remaining() {
+            return Err(self.out_of_bounds(len));
         }
-        self.cursor = self.cursor + len;
+        let mut buffer: Vec<u8> = vec![0; len as usize];
+        self.read_exact(&mut buffer)?;
         return Ok(buffer);
     }
+    pub fn read_string(&mut self, len: u64) -> io::Result<String> {
+        let bytes = self.read_bytes(len)?;
+        return String::from_utf8(bytes)
+            .map_err(|e| io::Error::new(ErrorKind::InvalidData, e));
+    }
     pub fn seek(&mut self, pos: u64) -> io::Result<()> {
-        if pos >= self.limit {
+        // seeking exactly to the end of input is fine, nothing can be read there though:
+        if pos > self.limit {
             return Err(io::Error::new(
                 ErrorKind::UnexpectedEof,
-                format!("EOF: seeking position {}, input length: {}", pos, self.limit)));
+                TruncatedRead {
+                    offset: self.offset + self.limit,
+                    requested: pos - self.limit,
+                    available: 0,
+                }));
         }
-        // TODO overflow check
-        self.file.file().seek(SeekFrom::Start(self.offset + pos))?;
+        // file cursor is positioned by the next read:
         self.cursor = pos;
         return Ok(());
     }
     pub fn ff(&mut self, len: u64) -> io::Result<()> {
-        // TODO maybe implement with SeekFrom::Current?
+        if len > self.remaining() {
+            return Err(self.out_of_bounds(len));
+        }
         return self.seek(self.cursor + len);
     }
+    /// Creates a child input starting at the cursor, clamped to the end of this input.
     pub fn section(&mut self, len: u64) -> Input<'f> {
         return Input {
             file: self.file,
-            offset: self.offset + self.cursor,
-            limit: len,
+            offset: self.position(),
+            limit: len.min(self.remaining()),
             cursor: 0,
         };
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BYTES: [u8; 8] = [0, 1, 2, 3, 4, 5, 6, 7];
+
+    // offset, requested and available bytes of a truncated read:
+    fn truncation(e: io::Error) -> (u64, u64, u64) {
+        let truncated = e.get_ref()
+            .and_then(|e| e.downcast_ref::<TruncatedRead>())
+            .expect("not a truncated read");
+        return (truncated.offset, truncated.requested, truncated.available);
+    }
+
+    #[test]
+    fn read_ending_exactly_at_end_of_input() {
+        let file = InputBox::from_bytes("read-to-end", "bin", &BYTES);
+        let mut input = Input::create(&file);
+        input.ff(4).unwrap();
+        assert_eq!(input.read_u32(&Endianness::Big).unwrap(), 0x04050607);
+        assert_eq!(input.remaining(), 0);
+        assert_eq!(truncation(input.read_u16(&Endianness::Big).unwrap_err()), (8, 2, 0));
+    }
+
+    #[test]
+    fn lengths_overflowing_cursor_are_rejected() {
+        let file = InputBox::from_bytes("overflow", "bin", &BYTES);
+        let mut input = Input::create(&file);
+        input.ff(2).unwrap();
+        assert_eq!(truncation(input.ff(u64::MAX).unwrap_err()), (2, u64::MAX, 6));
+        assert_eq!(truncation(input.read_bytes(u64::MAX).unwrap_err()), (2, u64::MAX, 6));
+        let mut section = input.section(u64::MAX);
+        assert_eq!(truncation(section.ff(u64::MAX - 1).unwrap_err()), (2, u64::MAX - 1, 6));
+        assert_eq!(input.position(), 2);
+    }
+
+    #[test]
+    fn section_longer_than_parent_is_clamped() {
+        let file = InputBox::from_bytes("section", "bin", &BYTES);
+        let mut input = Input::create(&file);
+        input.ff(2).unwrap();
+        let mut section = input.section(4);
+        section.ff(1).unwrap();
+        let mut nested = section.section(100);
+        assert_eq!(nested.remaining(), 3);
+        assert_eq!(nested.read_bytes(3).unwrap(), vec![3, 4, 5]);
+        assert_eq!(truncation(nested.read_bytes(1).unwrap_err()), (6, 1, 0));
+    }
+
+    #[test]
+    fn seek_to_limit_is_allowed_but_not_past_it() {
+        let file = InputBox::from_bytes("seek", "bin", &BYTES);
+        let mut input = Input::create(&file);
+        input.ff(2).unwrap();
+        let mut section = input.section(4);
+        section.seek(4).unwrap();
+        assert_eq!(section.remaining(), 0);
+        assert_eq!(section.position(), 6);
+        assert_eq!(truncation(section.seek(5).unwrap_err()), (6, 1, 0));
+        section.seek(0).unwrap();
+        assert_eq!(section.read_u16(&Endianness::Little).unwrap(), 0x0302);
+    }
+
+    #[test]
+    fn ff_past_end_keeps_cursor() {
+        let file = InputBox::from_bytes("ff", "bin", &BYTES);
+        let mut input = Input::create(&file);
+        input.ff(8).unwrap();
+        input.seek(5).unwrap();
+        assert_eq!(truncation(input.ff(4).unwrap_err()), (5, 4, 3));
+        assert_eq!(input.position(), 5);
+        assert_eq!(input.read_bytes(3).unwrap(), vec![5, 6, 7]);
+    }
+
+    #[test]
+    fn truncated_read_reports_absolute_offset() {
+        let file = InputBox::from_bytes("offset", "bin", &BYTES);
+        let mut input = Input::create(&file);
+        input.ff(3).unwrap();
+        let mut section = input.section(4);
+        section.ff(2).unwrap();
+        // the section ends at 7 while the file is 8 bytes long:
+        assert_eq!(truncation(section.read_u32(&Endianness::Big).unwrap_err()), (5, 4, 2));
+    }
+}
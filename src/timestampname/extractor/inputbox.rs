@@ -10,19 +10,27 @@ pub struct InputBox {
     file: File,
 }
 
+/// Path as text, new names are made of it. Traversal reaches any name, so paths that are not
+/// valid UTF-8 fail only their own file.
+pub fn utf8_file_name(path: &Path) -> Result<String, Failure> {
+    return path.to_str()
+        .map(|f| f.to_string())
+        .ok_or_else(|| Failure::unsupported(
+            path.to_string_lossy().to_string(),
+            "file path is not valid UTF-8".to_string()));
+}
+
 impl InputBox {
     pub fn create(path: &Path, ext: String) -> Result<InputBox, Failure> {
-        let file_name: String = path.file_name()
-            .and_then(|f| f.to_str())
-            .map(|f| f.to_string())
-            .expect("failed to create Input from path without filename");
+        // path relative to the working directory, files of subdirectories are told apart by it:
+        let file_name: String = utf8_file_name(path)?;
         let file = File::open(path)
-            .map_err(|e| Failure::file_failure_caused(
+            .map_err(|e| Failure::file_io(
                 file_name.to_string(),
                 "failed to open file".to_string(),
                 e))?;
         let file_size = file.metadata().map(|m| m.len())
-            .map_err(|e| Failure::file_failure_caused(
+            .map_err(|e| Failure::file_io(
                 file_name.to_string(),
                 "failed to get file metadata".to_string(),
                 e))?;
@@ -45,4 +53,15 @@ impl InputBox {
     pub fn file(&self) -> &File {
         return &self.file;
     }
+
+    /// File with the given content, removed from the file system right away,
+    /// it stays readable while open.
+    #[cfg(test)]
+    pub fn from_bytes(name: &str, ext: &str, bytes: &[u8]) -> InputBox {
+        let path = std::env::temp_dir().join(format!("timestampname-{}-{}.{}", std::process::id(), name, ext));
+        std::fs::write(&path, bytes).expect("failed to write test file");
+        let input_box = InputBox::create(&path, ext.to_string()).expect("failed to open test file");
+        std::fs::remove_file(&path).expect("failed to remove test file");
+        return input_box;
+    }
 }
@@ -2,8 +2,8 @@ use super::input::Input;
 use super::FileMetadata;
 use super::Failure;
 use super::Endianness;
+use super::ExtractorOptions;
 use crate::timestampname::extractor::tiff::tiff_extract_metadata_creation_timestamp;
-use std::io;
 
 // following resources were used to implement this parser:
 // https://www.media.mit.edu/pia/Research/deepview/exif.html
@@ -12,76 +12,66 @@ use std::io;
 
 const JPEG_SOI: u16 = 0xFFD8;
 const JPEG_APP1: u16 = 0xFFE1;
+const JPEG_SOS: u16 = 0xFFDA;
+const JPEG_EOI: u16 = 0xFFD9;
 const EXIF_HEADER_SUFFIX: u16 = 0x0000;
 
-#[derive(Debug)]
-enum JpegError {
-    Input {
-        description: String
-    },
-    Io {
-        operation: String,
-        cause: io::Error,
-    },
-}
-
-fn err_input(description: String) -> JpegError {
-    return JpegError::Input {
-        description
-    };
-}
-
-fn err_io(operation: String, cause: io::Error) -> JpegError {
-    return JpegError::Io {
-        operation,
-        cause,
-    };
-}
-
-fn jpeg_scan_for_app1<'a>(input: &'a mut Input) -> Result<Input<'a>, JpegError> {
+fn jpeg_scan_for_app1<'a>(input: &'a mut Input) -> Result<Input<'a>, Failure> {
+    let file_name = input.name().to_string();
     // checking JPEG SOI:
     let jpeg_soi = input.read_u16(&Endianness::Big)
-        .map_err(|e| err_io("reading jpeg header".to_string(), e))?;
+        .map_err(|e| Failure::file_io(file_name.to_string(), "reading jpeg header".to_string(), e))?;
     if jpeg_soi != JPEG_SOI {
-        return Err(err_input(format!("unexpected JPEG SOI: {}", jpeg_soi)));
+        return Err(Failure::malformed(file_name, format!("unexpected JPEG SOI: {}", jpeg_soi), 0));
     }
     // scrolling through fields until we find APP1:
     loop {
+        let field_offset = input.position();
         let field_marker = input.read_u16(&Endianness::Big)
-            .map_err(|e| err_io("reading jpeg field marker".to_string(), e))?;
+            .map_err(|e| Failure::file_io(file_name.to_string(), "reading jpeg field marker".to_string(), e))?;
+        if field_marker == JPEG_SOS || field_marker == JPEG_EOI {
+            // image data started, metadata segments can only precede it:
+            return Err(Failure::missing_timestamp(
+                file_name,
+                "JPEG does not have Exif APP1 field".to_string()));
+        }
         let field_length = input.read_u16(&Endianness::Big)
-            .map_err(|e| err_io("reading jpeg field length".to_string(), e))?;
-        if field_marker == JPEG_APP1 {
+            .map_err(|e| Failure::file_io(file_name.to_string(), "reading jpeg field length".to_string(), e))?;
+        if field_length < 2 {
+            return Err(Failure::malformed(
+                file_name,
+                format!("JPEG field length is too small: {}", field_length),
+                field_offset));
+        }
+        // APP1 is also used for XMP, which is all there is in files exported without Exif,
+        // such segments are skipped as any other:
+        if field_marker == JPEG_APP1 && field_length >= 8 {
             // APP1 marker found, checking Exif header:
-            let exif_header = input.read_string(4)
-                .map_err(|e| err_io("reading jpeg exif header".to_string(), e))?;
+            // other payloads may not be text:
+            let exif_header = input.read_bytes(4)
+                .map_err(|e| Failure::file_io(file_name.to_string(), "reading jpeg exif header".to_string(), e))?;
             let exif_header_suffix = input.read_u16(&Endianness::Big)
-                .map_err(|e| err_io("reading jpeg exif header suffix".to_string(), e))?;
-            if &exif_header != "Exif" || exif_header_suffix != EXIF_HEADER_SUFFIX {
-                return Err(err_input("JPEG APP1 field does not have valid Exif header".to_string()));
+                .map_err(|e| Failure::file_io(file_name.to_string(), "reading jpeg exif header suffix".to_string(), e))?;
+            if exif_header == b"Exif" && exif_header_suffix == EXIF_HEADER_SUFFIX {
+                // body is a valid TIFF,
+                // size decrements:
+                //   -2 field length
+                //   -4 exif header
+                //   -2 exif header suffix
+                let exif_input = input.section(field_length as u64 - 8);
+                return Ok(exif_input);
             }
-            // body is a valid TIFF,
-            // size decrements:
-            //   -2 field length
-            //   -4 exif header
-            //   -2 exif header suffix
-            let exif_input = input.section(field_length as u64 - 8);
-            return Ok(exif_input);
+            input.ff(field_length as u64 - 8)
+                .map_err(|e| Failure::file_io(file_name.to_string(), "fast-forward jpeg field".to_string(), e))?;
+            continue;
         }
         // length includes the length itself:
         input.ff(field_length as u64 - 2)
-            .map_err(|e| err_io("fast-forward jpeg field".to_string(), e))?;
+            .map_err(|e| Failure::file_io(file_name.to_string(), "fast-forward jpeg field".to_string(), e))?;
     }
 }
 
-pub fn jpeg_extract_metadata_creation_timestamp(input: &mut Input) -> Result<Option<FileMetadata>, Failure> {
-    let file_name = input.name().to_string();
-    let mut exif_input = jpeg_scan_for_app1(input)
-        .map_err(|e| match e {
-            JpegError::Input { description } =>
-                Failure::file_failure(file_name.to_string(), description),
-            JpegError::Io { operation, cause } =>
-                Failure::file_failure_caused(file_name.to_string(), operation, cause),
-        })?;
-    return tiff_extract_metadata_creation_timestamp(&mut exif_input);
+pub fn jpeg_extract_metadata_creation_timestamp(input: &mut Input, options: &ExtractorOptions) -> Result<Option<FileMetadata>, Failure> {
+    let mut exif_input = jpeg_scan_for_app1(input)?;
+    return tiff_extract_metadata_creation_timestamp(&mut exif_input, options);
 }
@@ -1,9 +1,24 @@
-use std::path::PathBuf;
+use std::fs;
+use std::path::Path;
+
+use chrono::DateTime;
+use chrono::Local;
 
 use super::failures::Failure;
+use super::failures::FailureKind;
+use super::filename::FileNamePattern;
+use super::filename::timestamp_from_file_name;
+use super::DatePriority;
+use super::Fallback;
+use super::ExifDateTag;
 use super::FileMetadata;
+use super::Mp4Header;
+use super::TimestampSource;
+use super::timestamp::Timestamp;
+use super::timezone::Mp4Clock;
 use super::extractor::input::Input;
 use super::extractor::inputbox::InputBox;
+use super::extractor::inputbox::utf8_file_name;
 
 mod inputbox;
 mod input;
@@ -13,18 +28,95 @@ mod mp4;
 mod cr3;
 mod jpeg;
 
-pub fn extract_metadata_creation_timestamp(path: &PathBuf, utc: bool) -> Result<Option<FileMetadata>, Failure> {
-    let ext: String = path.extension()
+pub use self::quicktime::QuickTimeBox;
+
+pub struct ExtractorOptions {
+    /// Clock of MP4 writers that are not recognized or have no rule.
+    pub mp4_default_clock: Mp4Clock,
+    /// Lowercase vendor names and their clocks, taking precedence over built-in rules.
+    pub mp4_clock_rules: Vec<(String, Mp4Clock)>,
+    pub date_priority: DatePriority,
+    /// Sources to try in order for files without timestamp in metadata, empty if such files should fail.
+    pub fallbacks: Vec<Fallback>,
+    pub file_name_patterns: Vec<FileNamePattern>,
+    /// Whether files of unsupported types get fallback timestamps, they are skipped otherwise.
+    pub include_unsupported: bool,
+}
+
+fn lowercase_extension(path: &Path) -> String {
+    return path.extension()
         .and_then(|x| x.to_str())
         .map_or("".to_string(), |x| x.to_lowercase());
+}
+
+pub fn extract_metadata_creation_timestamp(path: &Path, options: &ExtractorOptions) -> Result<Option<FileMetadata>, Failure> {
+    let ext: String = lowercase_extension(path);
+
+    let extracted = match ext.as_str() {
+        "nef" => tiff::tiff_extract_metadata_creation_timestamp(&mut Input::create(&InputBox::create(path, ext)?), options),
+        "dng" => tiff::tiff_extract_metadata_creation_timestamp(&mut Input::create(&InputBox::create(path, ext)?), options),
+        "mp4" => mp4::mp4_extract_metadata_creation_timestamp(&mut Input::create(&InputBox::create(path, ext)?), options),
+        "cr3" => cr3::cr3_extract_metadata_creation_timestamp(&mut Input::create(&InputBox::create(path, ext)?), options),
+        "jpg" => jpeg::jpeg_extract_metadata_creation_timestamp(&mut Input::create(&InputBox::create(path, ext)?), options),
+        "jpeg" => jpeg::jpeg_extract_metadata_creation_timestamp(&mut Input::create(&InputBox::create(path, ext)?), options),
+        _ if options.include_unsupported => {
+            utf8_file_name(path)?;
+            return Ok(options.fallbacks.iter()
+                .find_map(|f| fallback_timestamp(path, *f, options))
+                .and_then(|timestamp| fallback_metadata(path, timestamp)));
+        }
+        _ => Ok(None)
+    };
+    // structurally broken files still fail, only absent metadata falls back:
+    return match extracted {
+        Err(e) if e.kind() == FailureKind::MissingTimestamp => {
+            match options.fallbacks.iter().find_map(|f| fallback_timestamp(path, *f, options)) {
+                Some(timestamp) => Ok(fallback_metadata(path, timestamp)),
+                None => Err(e)
+            }
+        }
+        r => r
+    };
+}
+
+fn fallback_timestamp(path: &Path, fallback: Fallback, options: &ExtractorOptions) -> Option<Timestamp> {
+    let system_time = match fallback {
+        Fallback::FileName => {
+            let file_name: &str = path.file_name().and_then(|f| f.to_str())?;
+            return timestamp_from_file_name(file_name, &options.file_name_patterns);
+        }
+        Fallback::ModificationTime => fs::metadata(path).and_then(|m| m.modified()),
+        // statx on Linux, unsupported by some file systems:
+        Fallback::BirthTime => fs::metadata(path).and_then(|m| m.created()),
+    };
+    let source = match fallback {
+        Fallback::BirthTime => TimestampSource::BirthTime,
+        _ => TimestampSource::ModificationTime
+    };
+    // file system times are points in time, shown in local time zone:
+    let local: DateTime<Local> = DateTime::from(system_time.ok()?);
+    return Some(Timestamp::zoned(local.fixed_offset(), source));
+}
+
+fn fallback_metadata(path: &Path, timestamp: Timestamp) -> Option<FileMetadata> {
+    let file_name: &str = path.to_str()?;
+    return Some(FileMetadata {
+        file_name: file_name.to_string(),
+        timestamp,
+        camera_make: None,
+        camera_model: None,
+        camera_serial: None,
+        lens_model: None,
+        gps_timestamp: None,
+        extension: format!(".{}", lowercase_extension(path)),
+    });
+}
 
+/// Lists all boxes of QuickTime based files for diagnostics, None for other file types.
+pub fn quicktime_box_tree(path: &Path) -> Result<Option<Vec<QuickTimeBox>>, Failure> {
+    let ext: String = lowercase_extension(path);
     match ext.as_str() {
-        "nef" => tiff::tiff_extract_metadata_creation_timestamp(&mut Input::create(&InputBox::create(path, ext)?)),
-        "dng" => tiff::tiff_extract_metadata_creation_timestamp(&mut Input::create(&InputBox::create(path, ext)?)),
-        "mp4" => mp4::mp4_extract_metadata_creation_timestamp(&mut Input::create(&InputBox::create(path, ext)?), utc),
-        "cr3" => cr3::cr3_extract_metadata_creation_timestamp(&mut Input::create(&InputBox::create(path, ext)?)),
-        "jpg" => jpeg::jpeg_extract_metadata_creation_timestamp(&mut Input::create(&InputBox::create(path, ext)?)),
-        "jpeg" => jpeg::jpeg_extract_metadata_creation_timestamp(&mut Input::create(&InputBox::create(path, ext)?)),
+        "mp4" | "cr3" => Input::create(&InputBox::create(path, ext)?).quicktime_box_tree().map(Some),
         _ => Ok(None)
     }
 }
@@ -1,79 +1,596 @@
-use chrono::{Local, TimeZone, Utc};
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+use chrono::{DateTime, FixedOffset, Local, NaiveDateTime, TimeZone, Utc};
 
 use super::Endianness;
+use super::ExtractorOptions;
 use super::Failure;
 use super::FileMetadata;
 use super::Input;
+use super::Mp4Header;
+use super::Timestamp;
+use super::TimestampSource;
+use crate::timestampname::timezone::Mp4Clock;
 
 // pre-calculated duration between 1904-01-01 and 1970-01-01:
 const MP4_EPOCH_OFFSET: u64 = 2082844800;
+// two days, more than the largest UTC offset:
+const MP4_FUTURE_MARGIN: u64 = 2 * 24 * 3600;
+
+// signs of the writer, lowercase vendor names:
+enum VendorMarker {
+    /// Major or compatible brand of ftyp box.
+    Brand(&'static [u8; 4]),
+    /// Vendor specific atom inside moov/udta box.
+    UserDataAtom(&'static [u8; 4]),
+    /// Part of a handler name of any track.
+    HandlerName(&'static str),
+}
+
+// handler names like "Core Media" come from system libraries and are shared by many writers,
+// QuickTime brand is the weakest sign, so it is checked last:
+const VENDOR_MARKERS: [(&str, VendorMarker); 8] = [
+    ("sony", VendorMarker::Brand(b"XAVC")),
+    ("canon", VendorMarker::Brand(b"CAEP")),
+    ("samsung", VendorMarker::UserDataAtom(b"smta")),
+    ("gopro", VendorMarker::UserDataAtom(b"GPMF")),
+    ("gopro", VendorMarker::UserDataAtom(b"FIRM")),
+    ("dji", VendorMarker::HandlerName("DJI")),
+    ("gopro", VendorMarker::HandlerName("GoPro")),
+    ("apple", VendorMarker::Brand(b"qt  ")),
+];
+
+// how known vendors store creation times, phones tend to follow the specification,
+// action cameras and drones do not know their time zone:
+const VENDOR_CLOCKS: [(&str, Mp4Clock); 5] = [
+    ("apple", Mp4Clock::Utc),
+    ("samsung", Mp4Clock::Utc),
+    ("google", Mp4Clock::Utc),
+    ("dji", Mp4Clock::Local),
+    ("gopro", Mp4Clock::Local),
+];
+
+struct Mp4Writer {
+    // lowercase, make tag value if no marker matched:
+    vendor: Option<String>,
+    make: Option<String>,
+    model: Option<String>,
+}
+
+// QuickTime user data text: 2 bytes of length, 2 bytes of language, text:
+fn read_user_data_text(udta_box: &mut Input, box_type: &[u8; 4]) -> Result<Option<String>, Failure> {
+    let file_name = udta_box.name().to_string();
+    udta_box.seek(0)
+        .map_err(|e| Failure::file_io(file_name.to_string(), "rewinding udta box".to_string(), e))?;
+    let mut text_box = match udta_box.quicktime_search_raw_box(box_type)? {
+        Some(b) => b,
+        None => return Ok(None)
+    };
+    let length = text_box.read_u16(&Endianness::Big)
+        .map_err(|e| Failure::file_io(file_name.to_string(), "reading user data text length".to_string(), e))?;
+    text_box.ff(2)
+        .map_err(|e| Failure::file_io(file_name.to_string(), "skipping user data text language".to_string(), e))?;
+    let text = text_box.read_bytes(length as u64)
+        .map_err(|e| Failure::file_io(file_name.to_string(), "reading user data text".to_string(), e))?;
+    let text = String::from_utf8_lossy(&text).trim_matches(|c: char| c == '\0' || c.is_whitespace()).to_string();
+    return Ok(if text.is_empty() { None } else { Some(text) });
+}
+
+fn read_brands(input: &mut Input) -> Result<Vec<[u8; 4]>, Failure> {
+    let file_name = input.name().to_string();
+    let mut ftyp_box = match input.quicktime_search_box("ftyp")? {
+        Some(b) => b,
+        None => return Ok(Vec::new())
+    };
+    let mut brands: Vec<[u8; 4]> = Vec::new();
+    // major brand, minor version, compatible brands:
+    let major_brand = ftyp_box.read_u32(&Endianness::Big)
+        .map_err(|e| Failure::file_io(file_name.to_string(), "reading ftyp major brand".to_string(), e))?;
+    brands.push(major_brand.to_be_bytes());
+    ftyp_box.ff(4)
+        .map_err(|e| Failure::file_io(file_name.to_string(), "skipping ftyp minor version".to_string(), e))?;
+    while ftyp_box.remaining() >= 4 {
+        let brand = ftyp_box.read_u32(&Endianness::Big)
+            .map_err(|e| Failure::file_io(file_name.to_string(), "reading ftyp compatible brand".to_string(), e))?;
+        brands.push(brand.to_be_bytes());
+    }
+    return Ok(brands);
+}
+
+fn read_handler_names(moov_box: &mut Input) -> Result<Vec<String>, Failure> {
+    let file_name = moov_box.name().to_string();
+    let mut names: Vec<String> = Vec::new();
+    moov_box.seek(0)
+        .map_err(|e| Failure::file_io(file_name.to_string(), "rewinding moov box".to_string(), e))?;
+    loop {
+        let trak_length = {
+            let mut trak_box = match moov_box.quicktime_search_box("trak")? {
+                Some(b) => b,
+                None => break
+            };
+            let trak_length = trak_box.remaining();
+            if let Some(mut mdia_box) = trak_box.quicktime_search_box("mdia")? {
+                if let Some(mut hdlr_box) = mdia_box.quicktime_search_box("hdlr")? {
+                    // version and flags, pre-defined, handler type, 3 reserved, name:
+                    let name_length = hdlr_box.remaining().saturating_sub(24);
+                    let name = hdlr_box.ff(24)
+                        .and_then(|_| hdlr_box.read_bytes(name_length))
+                        .map_err(|e| Failure::file_io(file_name.to_string(), "reading hdlr name".to_string(), e))?;
+                    names.push(String::from_utf8_lossy(&name).to_string());
+                }
+            }
+            trak_length
+        };
+        // search leaves the cursor at the start of the found box body:
+        moov_box.ff(trak_length)
+            .map_err(|e| Failure::file_io(file_name.to_string(), "fast-forward trak box".to_string(), e))?;
+    }
+    return Ok(names);
+}
+
+// https://developer.apple.com/documentation/quicktime-file-format/user_data_atoms
+fn mp4_detect_writer(input: &mut Input) -> Result<Mp4Writer, Failure> {
+    let file_name = input.name().to_string();
+    input.seek(0)
+        .map_err(|e| Failure::file_io(file_name.to_string(), "rewinding file".to_string(), e))?;
+    let brands = read_brands(input)?;
+    input.seek(0)
+        .map_err(|e| Failure::file_io(file_name.to_string(), "rewinding file".to_string(), e))?;
+    let mut moov_box = match input.quicktime_search_box("moov")? {
+        Some(b) => b,
+        None => return Ok(Mp4Writer { vendor: None, make: None, model: None })
+    };
+    let handler_names = read_handler_names(&mut moov_box)?;
+    moov_box.seek(0)
+        .map_err(|e| Failure::file_io(file_name.to_string(), "rewinding moov box".to_string(), e))?;
+    let (user_data_atoms, make, model) = match moov_box.quicktime_search_box("udta")? {
+        Some(mut udta_box) => {
+            let atoms: Vec<String> = udta_box.quicktime_box_tree()?.into_iter()
+                .filter(|b| b.depth == 0)
+                .map(|b| b.box_type)
+                .collect();
+            let make = read_user_data_text(&mut udta_box, b"\xA9mak")?;
+            let model = read_user_data_text(&mut udta_box, b"\xA9mod")?;
+            (atoms, make, model)
+        }
+        None => (Vec::new(), None, None)
+    };
+    // Apple devices write make and model as metadata keys instead:
+    let make = match make {
+        Some(m) => Some(m),
+        None => read_apple_key(&mut moov_box, b"com.apple.quicktime.make")?
+    };
+    let model = match model {
+        Some(m) => Some(m),
+        None => read_apple_key(&mut moov_box, b"com.apple.quicktime.model")?
+    };
+
+    let marker_vendor: Option<String> = VENDOR_MARKERS.iter()
+        .find(|(_, marker)| match marker {
+            VendorMarker::Brand(brand) => brands.contains(brand),
+            // box types are listed as latin1 strings:
+            VendorMarker::UserDataAtom(atom) => user_data_atoms.iter()
+                .any(|a| a.chars().map(|c| c as u8).eq(atom.iter().copied())),
+            VendorMarker::HandlerName(name) => handler_names.iter().any(|h| h.contains(name)),
+        })
+        .map(|(vendor, _)| vendor.to_string());
+    // make tag is the most reliable sign, known vendors are matched within it,
+    // so that "Samsung Electronics" is still samsung:
+    let make_vendor: Option<String> = make.as_ref().map(|m| {
+        let m = m.to_lowercase();
+        return VENDOR_CLOCKS.iter().map(|(vendor, _)| *vendor)
+            .chain(VENDOR_MARKERS.iter().map(|(vendor, _)| *vendor))
+            .find(|vendor| m.contains(vendor))
+            .map_or(m.to_string(), |vendor| vendor.to_string());
+    });
+    return Ok(Mp4Writer {
+        vendor: make_vendor.or(marker_vendor),
+        make,
+        model,
+    });
+}
+
+// rules given by the user win over built-in ones:
+fn writer_clock(writer: &Mp4Writer, options: &ExtractorOptions) -> Mp4Clock {
+    let vendor: &str = match &writer.vendor {
+        Some(v) => v,
+        None => return options.mp4_default_clock
+    };
+    return options.mp4_clock_rules.iter()
+        .map(|(v, clock)| (v.as_str(), *clock))
+        .chain(VENDOR_CLOCKS.iter().copied())
+        .find(|(v, _)| *v == vendor)
+        .map_or(options.mp4_default_clock, |(_, clock)| clock);
+}
+
+// timestamps before unix epoch are zero (1904-01-01) or garbage written by cameras without a clock,
+// so are the ones in the future, allowing for local clocks ahead of UTC and clocks running fast:
+fn plausible_mp4_timestamp(timestamp: u64) -> bool {
+    let now: u64 = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    return timestamp > MP4_EPOCH_OFFSET && timestamp <= now + MP4_EPOCH_OFFSET + MP4_FUTURE_MARGIN;
+}
+
+/// Creation time found in one of the fallback locations.
+enum Mp4FallbackTime {
+    /// Seconds since 1904 from a header box, absolute offset of the value.
+    Header(u64, Mp4Header, u64),
+    /// Date text and its source.
+    Text(String, TimestampSource),
+}
+
+// mvhd, tkhd and mdhd start with the same fields:
+// version and flags, creation time, modification time, 32 or 64 bits each depending on version.
+fn read_header_creation_time(header_box: &mut Input, box_name: &str) -> Result<(u64, u64), Failure> {
+    let file_name = header_box.name().to_string();
+    let version_and_flags = header_box.read_u32(&Endianness::Big)
+        .map_err(|e| Failure::file_io(
+            file_name.to_string(),
+            format!("failed to read {} version", box_name),
+            e))?;
+    let offset = header_box.position();
+    let creation_time: u64 = match version_and_flags >> 24 {
+        0 => header_box.read_u32(&Endianness::Big).map(|t| t as u64),
+        1 => header_box.read_u64(&Endianness::Big),
+        v => return Err(Failure::unsupported(
+            file_name.to_string(),
+            format!("unsupported {} version: {}", box_name, v)))
+    }.map_err(|e| Failure::file_io(
+        file_name.to_string(),
+        format!("failed to read {} creation time", box_name),
+        e))?;
+    return Ok((creation_time, offset));
+}
+
+// first plausible creation time of the track headers, then of the media headers:
+fn read_track_creation_time(moov_box: &mut Input) -> Result<Option<Mp4FallbackTime>, Failure> {
+    let file_name = moov_box.name().to_string();
+    for header in [Mp4Header::Track, Mp4Header::Media] {
+        moov_box.seek(0)
+            .map_err(|e| Failure::file_io(file_name.to_string(), "rewinding moov box".to_string(), e))?;
+        loop {
+            let (trak_length, found) = {
+                let mut trak_box = match moov_box.quicktime_search_box("trak")? {
+                    Some(b) => b,
+                    None => break
+                };
+                let trak_length = trak_box.remaining();
+                let header_box = match header {
+                    Mp4Header::Media => match trak_box.quicktime_search_box("mdia")? {
+                        Some(mut mdia_box) => mdia_box.quicktime_search_box("mdhd")?
+                            .map(|mut b| read_header_creation_time(&mut b, "mdhd")),
+                        None => None
+                    },
+                    _ => trak_box.quicktime_search_box("tkhd")?
+                        .map(|mut b| read_header_creation_time(&mut b, "tkhd")),
+                };
+                let found = match header_box {
+                    Some(time) => Some(time?).filter(|(t, _)| plausible_mp4_timestamp(*t)),
+                    None => None
+                };
+                (trak_length, found)
+            };
+            if let Some((creation_time, offset)) = found {
+                return Ok(Some(Mp4FallbackTime::Header(creation_time, header, offset)));
+            }
+            // search leaves the cursor at the start of the found box body:
+            moov_box.ff(trak_length)
+                .map_err(|e| Failure::file_io(file_name.to_string(), "fast-forward trak box".to_string(), e))?;
+        }
+    }
+    return Ok(None);
+}
+
+// https://developer.apple.com/documentation/quicktime-file-format/metadata_item_keys_atom
+fn read_apple_key(moov_box: &mut Input, key: &[u8]) -> Result<Option<String>, Failure> {
+    let file_name = moov_box.name().to_string();
+    moov_box.seek(0)
+        .map_err(|e| Failure::file_io(file_name.to_string(), "rewinding moov box".to_string(), e))?;
+    let mut meta_box = match moov_box.quicktime_search_box("meta")? {
+        Some(b) => b,
+        None => return Ok(None)
+    };
+    // ISO meta box is a full box, QuickTime one is not, telling them apart by zero version and flags:
+    let version_and_flags = meta_box.read_u32(&Endianness::Big)
+        .map_err(|e| Failure::file_io(file_name.to_string(), "reading meta box version".to_string(), e))?;
+    let children_start: u64 = if version_and_flags == 0 { 4 } else { 0 };
+    meta_box.seek(children_start)
+        .map_err(|e| Failure::file_io(file_name.to_string(), "rewinding meta box".to_string(), e))?;
+
+    // keys are numbered from 1 in the order of the keys box:
+    let mut key_index: Option<u32> = None;
+    if let Some(mut keys_box) = meta_box.quicktime_search_box("keys")? {
+        let entry_count = keys_box.ff(4)
+            .and_then(|_| keys_box.read_u32(&Endianness::Big))
+            .map_err(|e| Failure::file_io(file_name.to_string(), "reading keys count".to_string(), e))?;
+        for index in 1..=entry_count {
+            let key_size = keys_box.read_u32(&Endianness::Big)
+                .map_err(|e| Failure::file_io(file_name.to_string(), "reading key size".to_string(), e))?;
+            if key_size < 8 {
+                return Err(Failure::malformed(
+                    file_name.to_string(),
+                    format!("key size {} is smaller than its header", key_size),
+                    keys_box.position() - 4));
+            }
+            // namespace, usually mdta, followed by the key name:
+            let key_name = keys_box.ff(4)
+                .and_then(|_| keys_box.read_bytes(key_size as u64 - 8))
+                .map_err(|e| Failure::file_io(file_name.to_string(), "reading key name".to_string(), e))?;
+            if key_name == key {
+                key_index = Some(index);
+                break;
+            }
+        }
+    }
+    let key_index: u32 = match key_index {
+        Some(i) => i,
+        None => return Ok(None)
+    };
 
-fn format_mp4_timestamp(timestamp: u64, input: &mut Input, utc: bool) -> Result<Option<FileMetadata>, Failure> {
-    let ts_in_unix_epoch = timestamp - MP4_EPOCH_OFFSET;
-    if ts_in_unix_epoch > i64::max_value() as u64 {
-        return Err(Failure::file_failure(
+    meta_box.seek(children_start)
+        .map_err(|e| Failure::file_io(file_name.to_string(), "rewinding meta box".to_string(), e))?;
+    let mut ilst_box = match meta_box.quicktime_search_box("ilst")? {
+        Some(b) => b,
+        None => return Ok(None)
+    };
+    // items are boxes with the key index as type, value is in the nested data box:
+    let mut item_box = match ilst_box.quicktime_search_raw_box(&key_index.to_be_bytes())? {
+        Some(b) => b,
+        None => return Ok(None)
+    };
+    let mut data_box = match item_box.quicktime_search_box("data")? {
+        Some(b) => b,
+        None => return Ok(None)
+    };
+    // type indicator and locale precede the value:
+    let value_length = data_box.remaining().saturating_sub(8);
+    let value = data_box.ff(8)
+        .and_then(|_| data_box.read_bytes(value_length))
+        .map_err(|e| Failure::file_io(file_name.to_string(), "reading metadata item value".to_string(), e))?;
+    let value = String::from_utf8_lossy(&value).trim_matches(|c: char| c == '\0' || c.is_whitespace()).to_string();
+    return Ok(if value.is_empty() { None } else { Some(value) });
+}
+
+fn read_fallback_time(input: &mut Input) -> Result<Option<Mp4FallbackTime>, Failure> {
+    let file_name = input.name().to_string();
+    input.seek(0)
+        .map_err(|e| Failure::file_io(file_name.to_string(), "rewinding file".to_string(), e))?;
+    let mut moov_box = match input.quicktime_search_box("moov")? {
+        Some(b) => b,
+        None => return Ok(None)
+    };
+    if let Some(time) = read_track_creation_time(&mut moov_box)? {
+        return Ok(Some(time));
+    }
+    moov_box.seek(0)
+        .map_err(|e| Failure::file_io(file_name.to_string(), "rewinding moov box".to_string(), e))?;
+    if let Some(mut udta_box) = moov_box.quicktime_search_box("udta")? {
+        if let Some(day) = read_user_data_text(&mut udta_box, b"\xA9day")? {
+            return Ok(Some(Mp4FallbackTime::Text(day, TimestampSource::Mp4UserDataDate)));
+        }
+    }
+    return Ok(read_apple_key(&mut moov_box, b"com.apple.quicktime.creationdate")?
+        .map(|date| Mp4FallbackTime::Text(date, TimestampSource::Mp4AppleCreationDate)));
+}
+
+/// Parses ISO 8601 dates as written into ©day and Apple keys,
+/// like `2020-05-17T11:00:00+0200`, with optional fraction of a second and zone.
+fn parse_mp4_date(text: &str) -> Option<(NaiveDateTime, Option<FixedOffset>)> {
+    let text = text.trim_matches(|c: char| c == '\0' || c.is_whitespace());
+    let zoned = match text.strip_suffix('Z') {
+        Some(utc) => format!("{}+0000", utc),
+        None => text.to_string()
+    };
+    if let Ok(date) = DateTime::parse_from_str(&zoned, "%Y-%m-%dT%H:%M:%S%.f%z") {
+        return Some((date.naive_local(), Some(*date.offset())));
+    }
+    return NaiveDateTime::parse_from_str(text, "%Y-%m-%dT%H:%M:%S%.f").ok()
+        .map(|date| (date, None));
+}
+
+fn mp4_date_metadata(text: &str,
+                     source: TimestampSource,
+                     input: &Input,
+                     writer: Mp4Writer) -> Result<Option<FileMetadata>, Failure> {
+    let (date, utc_offset) = parse_mp4_date(text)
+        .ok_or_else(|| Failure::missing_timestamp(
+            input.name().to_string(),
+            format!("mp4 date has unrecognized format: {}", text)))?;
+    return Ok(Some(FileMetadata {
+        file_name: input.name().to_string(),
+        timestamp: Timestamp::create(date, utc_offset, source),
+        camera_make: writer.make,
+        camera_model: writer.model,
+        camera_serial: None,
+        lens_model: None,
+        gps_timestamp: None,
+        extension: format!(".{}", input.ext()),
+    }));
+}
+
+fn mp4_timestamp_metadata(timestamp: u64,
+                          header: Mp4Header,
+                          input: &Input,
+                          offset: u64,
+                          writer: Mp4Writer,
+                          clock: Mp4Clock) -> Result<Option<FileMetadata>, Failure> {
+    let ts_in_unix_epoch = timestamp.checked_sub(MP4_EPOCH_OFFSET)
+        .ok_or_else(|| Failure::malformed(
+            input.name().to_string(),
+            format!("mp4 timestamp is before 1970: {}", timestamp),
+            offset))?;
+    if ts_in_unix_epoch > i64::MAX as u64 {
+        return Err(Failure::malformed(
             input.name().to_string(),
-            format!("mp4 timestamp overflows i64: {}", ts_in_unix_epoch)));
+            format!("mp4 timestamp overflows i64: {}", ts_in_unix_epoch),
+            offset));
     }
     let ts_casted = ts_in_unix_epoch as i64;
-    let formatted = match utc {
-        true => Utc.timestamp(ts_casted, 0).format("%Y%m%d-%H%M%S").to_string(),
-        false => Local.timestamp(ts_casted, 0).format("%Y%m%d-%H%M%S").to_string(),
-    };
+    // local clock timestamps are wall clock times of unknown zone:
+    let source = TimestampSource::Mp4CreationTime(header, clock);
+    let timestamp = match clock {
+        Mp4Clock::Local => Utc.timestamp_opt(ts_casted, 0).single()
+            .map(|t| Timestamp::create(t.naive_utc(), None, source)),
+        Mp4Clock::Utc => Local.timestamp_opt(ts_casted, 0).single()
+            .map(|t| Timestamp::zoned(t.fixed_offset(), source)),
+    }.ok_or_else(|| Failure::malformed(
+        input.name().to_string(),
+        format!("mp4 timestamp is out of range: {}", ts_casted),
+        offset))?;
     return Ok(Some(FileMetadata {
         file_name: input.name().to_string(),
-        creation_timestamp: formatted,
+        timestamp,
+        camera_make: writer.make,
+        camera_model: writer.model,
+        camera_serial: None,
+        lens_model: None,
+        gps_timestamp: None,
         extension: format!(".{}", input.ext()),
     }));
 }
 
-pub fn mp4_extract_metadata_creation_timestamp(input: &mut Input, utc: bool) -> Result<Option<FileMetadata>, Failure> {
+pub fn mp4_extract_metadata_creation_timestamp(input: &mut Input, options: &ExtractorOptions) -> Result<Option<FileMetadata>, Failure> {
     let file_name = input.name().to_string();
-    let mut moov_box = input.quicktime_search_box("moov")
-        .map_err(|e| Failure::file_failure_caused(
+    let mut moov_box = input.quicktime_search_box("moov")?
+        .ok_or_else(|| Failure::missing_timestamp(
             file_name.to_string(),
-            "moov box not found".to_string(),
-            e))?;
-    let mut mvhd_box = moov_box.quicktime_search_box("mvhd")
-        .map_err(|e| Failure::file_failure_caused(
+            "moov box not found".to_string()))?;
+    let mut mvhd_box = moov_box.quicktime_search_box("mvhd")?
+        .ok_or_else(|| Failure::missing_timestamp(
             file_name.to_string(),
-            "mvhd box not found".to_string(),
-            e))?;
-    let mvhd_version_and_flags = mvhd_box.read_u32(&Endianness::Big)
-        .map_err(|e| Failure::file_failure_caused(
+            "mvhd box not found".to_string()))?;
+    let (creation_time, creation_time_offset) = read_header_creation_time(&mut mvhd_box, "mvhd")?;
+    // detection is a best guess, broken vendor atoms should not fail a file with valid timestamp:
+    let writer = mp4_detect_writer(input)
+        .unwrap_or(Mp4Writer { vendor: None, make: None, model: None });
+    let clock = writer_clock(&writer, options);
+    if plausible_mp4_timestamp(creation_time) {
+        return mp4_timestamp_metadata(creation_time, Mp4Header::Movie, input, creation_time_offset, writer, clock);
+    }
+    return match read_fallback_time(input)? {
+        Some(Mp4FallbackTime::Header(time, header, offset)) =>
+            mp4_timestamp_metadata(time, header, input, offset, writer, clock),
+        Some(Mp4FallbackTime::Text(text, source)) =>
+            mp4_date_metadata(&text, source, input, writer),
+        None => Err(Failure::missing_timestamp(
             file_name.to_string(),
-            "failed to read mvhd version".to_string(),
-            e))?;
-    let mvhd_version = mvhd_version_and_flags >> 24;
-    match mvhd_version {
-        0 => {
-            let creation_time = input.read_u32(&Endianness::Big)
-                .map_err(|e| Failure::file_failure_caused(
-                    file_name.to_string(),
-                    "failed to read creation time".to_string(),
-                    e))?;
-            let modification_time = input.read_u32(&Endianness::Big)
-                .map_err(|e| Failure::file_failure_caused(
-                    file_name.to_string(),
-                    "failed to read modification time".to_string(),
-                    e))?;
-            return format_mp4_timestamp(creation_time as u64, input, utc);
-        }
-        1 => {
-            let creation_time = input.read_u64(&Endianness::Big)
-                .map_err(|e| Failure::file_failure_caused(
-                    file_name.to_string(),
-                    "failed to read creation time".to_string(),
-                    e))?;
-            let modification_time = input.read_u64(&Endianness::Big)
-                .map_err(|e| Failure::file_failure_caused(
-                    file_name.to_string(),
-                    "failed to read modification time".to_string(),
-                    e))?;
-            return format_mp4_timestamp(creation_time, input, utc);
+            format!("mvhd creation time is not plausible: {}, no other creation time found", creation_time)))
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::*;
+    use super::super::InputBox;
+
+    // 2020-05-17 11:00:00 UTC:
+    const CREATION_TIME: u64 = 1589713200 + MP4_EPOCH_OFFSET;
+
+    fn boxed(box_type: &[u8; 4], body: &[u8]) -> Vec<u8> {
+        let mut bytes: Vec<u8> = ((body.len() + 8) as u32).to_be_bytes().to_vec();
+        bytes.extend_from_slice(box_type);
+        bytes.extend_from_slice(body);
+        return bytes;
+    }
+
+    fn ftyp() -> Vec<u8> {
+        return boxed(b"ftyp", b"isom\0\0\0\0isommp42");
+    }
+
+    // version and flags, creation and modification time, timescale, duration, rest of the fields:
+    fn mvhd(version: u8, creation_time: u64) -> Vec<u8> {
+        let mut body: Vec<u8> = vec![version, 0, 0, 0];
+        match version {
+            0 => {
+                body.extend_from_slice(&(creation_time as u32).to_be_bytes());
+                body.extend_from_slice(&(creation_time as u32).to_be_bytes());
+                body.extend_from_slice(&1000u32.to_be_bytes());
+                body.extend_from_slice(&0u32.to_be_bytes());
+            }
+            _ => {
+                body.extend_from_slice(&creation_time.to_be_bytes());
+                body.extend_from_slice(&creation_time.to_be_bytes());
+                body.extend_from_slice(&1000u32.to_be_bytes());
+                body.extend_from_slice(&0u64.to_be_bytes());
+            }
         }
-        _ => return Err(Failure::file_failure(
-            file_name.to_string(),
-            format!("unsupported mvhd version: {}", mvhd_version)))
+        body.extend_from_slice(&[0; 80]);
+        return boxed(b"mvhd", &body);
+    }
+
+    fn options() -> ExtractorOptions {
+        return ExtractorOptions {
+            // timestamps are taken as they are, regardless of the zone tests run in:
+            mp4_default_clock: Mp4Clock::Local,
+            mp4_clock_rules: Vec::new(),
+            date_priority: Default::default(),
+            fallbacks: Vec::new(),
+            file_name_patterns: Vec::new(),
+            include_unsupported: false,
+        };
+    }
+
+    fn extract(name: &str, bytes: &[u8]) -> Result<Option<FileMetadata>, Failure> {
+        let file = InputBox::from_bytes(name, "mp4", bytes);
+        return mp4_extract_metadata_creation_timestamp(&mut Input::create(&file), &options());
+    }
+
+    fn assert_creation_time(md: Result<Option<FileMetadata>, Failure>) {
+        let timestamp = md.unwrap().unwrap().timestamp;
+        let expected = NaiveDate::from_ymd_opt(2020, 5, 17).unwrap().and_hms_opt(11, 0, 0).unwrap();
+        assert_eq!(timestamp.local, expected);
+        assert_eq!(timestamp.source, TimestampSource::Mp4CreationTime(Mp4Header::Movie, Mp4Clock::Local));
+    }
+
+    #[test]
+    fn mvhd_version_0() {
+        let file = [ftyp(), boxed(b"moov", &mvhd(0, CREATION_TIME))].concat();
+        assert_creation_time(extract("mvhd-v0", &file));
+    }
+
+    #[test]
+    fn mvhd_version_1() {
+        let file = [ftyp(), boxed(b"moov", &mvhd(1, CREATION_TIME))].concat();
+        assert_creation_time(extract("mvhd-v1", &file));
+    }
+
+    #[test]
+    fn large_box_before_moov() {
+        // size 1 is followed by the 64-bit size including the 16 bytes of the header:
+        let mut mdat: Vec<u8> = 1u32.to_be_bytes().to_vec();
+        mdat.extend_from_slice(b"mdat");
+        mdat.extend_from_slice(&(16u64 + 32).to_be_bytes());
+        mdat.extend_from_slice(&[0xFF; 32]);
+        let file = [ftyp(), mdat, boxed(b"moov", &mvhd(1, CREATION_TIME))].concat();
+        assert_creation_time(extract("large-box", &file));
+    }
+
+    #[test]
+    fn last_box_extending_to_end_of_file() {
+        // size 0 means the box takes the rest of the file:
+        let mut moov: Vec<u8> = 0u32.to_be_bytes().to_vec();
+        moov.extend_from_slice(b"moov");
+        moov.extend_from_slice(&mvhd(0, CREATION_TIME));
+        let file = [ftyp(), boxed(b"free", &[0; 8]), moov].concat();
+        assert_creation_time(extract("to-end", &file));
+    }
+
+    #[test]
+    fn mvhd_fields_are_read_from_mvhd_box() {
+        let file = InputBox::from_bytes("cursors", "mp4", &[ftyp(), boxed(b"moov", &mvhd(1, CREATION_TIME))].concat());
+        let mut input = Input::create(&file);
+        // body of the mvhd box starts after ftyp and the headers of moov and mvhd:
+        input.seek(24 + 8 + 8).unwrap();
+        let mut mvhd_box = input.section(112);
+        // the outer input moves the shared file cursor elsewhere:
+        input.seek(0).unwrap();
+        assert_eq!(input.read_u32(&Endianness::Big).unwrap(), 24);
+        let (creation_time, offset) = read_header_creation_time(&mut mvhd_box, "mvhd").unwrap();
+        assert_eq!(creation_time, CREATION_TIME);
+        assert_eq!(offset, 24 + 8 + 8 + 4);
+        // and reads through the outer input continue where it left:
+        assert_eq!(&input.read_bytes(4).unwrap(), b"ftyp");
     }
 }
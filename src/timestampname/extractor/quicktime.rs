@@ -1,55 +1,163 @@
-use std::io;
-
 use super::Endianness;
+use super::Failure;
 use super::input::Input;
 
 // following documents were used to implement this parser:
 // http://l.web.umkc.edu/lizhu/teaching/2016sp.video-communication/ref/mp4.pdf
 // https://mpeg.chiariglione.org/standards/mpeg-4/iso-base-media-file-format
 
+// boxes that consist of nested boxes only:
+const CONTAINER_BOXES: [&[u8; 4]; 12] = [
+    b"moov", b"trak", b"mdia", b"minf", b"stbl", b"udta",
+    b"edts", b"dinf", b"mvex", b"moof", b"traf", b"meta"];
+
+pub struct QuickTimeBox {
+    pub box_type: String,
+    /// Absolute offset of the box header within the file.
+    pub offset: u64,
+    /// Box size including the header.
+    pub size: u64,
+    /// Nesting level, 0 for top level boxes.
+    pub depth: usize,
+}
+
+struct BoxHeader {
+    box_type: [u8; 4],
+    offset: u64,
+    header_length: u64,
+    body_length: u64,
+}
+
+// box types are 4 bytes, not necessarily valid UTF-8 (for example ©day):
+fn box_type_to_string(box_type: &[u8]) -> String {
+    return box_type.iter().map(|b| *b as char).collect();
+}
+
 impl<'f> Input<'f> {
-    fn quicktime_scan_for_box(&mut self, name: &str,
-                              uuid: Option<(u64, u64)>) -> io::Result<Input<'f>> {
-        // TODO this infinite loop will throw EOF if the box will not be found
-        loop {
-            let mut box_length: u64 = self.read_u32(&Endianness::Big)? as u64;
-            let box_type: String = self.read_string(4)?;
-            // checking for large box:
-            if box_length == 1 {
-                box_length = self.read_u64(&Endianness::Big)?;
-                // box length includes header, have to make adjustments:
-                // 4 bytes for box length
-                // 4 bytes for box type
-                // 8 bytes for box large length
-                box_length = box_length - 16;
-            } else {
-                // box length includes header, have to make adjustments:
-                // 4 bytes for box length
-                // 4 bytes for box type
-                box_length = box_length - 8;
+    /// Reads the next box header, returns None if the end of input is reached.
+    fn quicktime_read_box_header(&mut self) -> Result<Option<BoxHeader>, Failure> {
+        if self.remaining() == 0 {
+            return Ok(None);
+        }
+        let offset = self.position();
+        let mut box_length: u64 = self.read_u32(&Endianness::Big)
+            .map_err(|e| Failure::file_io(self.name().to_string(), "reading box length".to_string(), e))? as u64;
+        let type_bytes = self.read_bytes(4)
+            .map_err(|e| Failure::file_io(self.name().to_string(), "reading box type".to_string(), e))?;
+        let box_type: [u8; 4] = [type_bytes[0], type_bytes[1], type_bytes[2], type_bytes[3]];
+        // 4 bytes for box length
+        // 4 bytes for box type
+        let mut header_length: u64 = 8;
+        match box_length {
+            // box extends to the end of enclosing input:
+            0 => box_length = header_length + self.remaining(),
+            // large box, 8 more bytes for box large length:
+            1 => {
+                box_length = self.read_u64(&Endianness::Big)
+                    .map_err(|e| Failure::file_io(self.name().to_string(), "reading box large length".to_string(), e))?;
+                header_length = 16;
             }
-            if &box_type == name {
+            _ => {}
+        }
+        if box_length < header_length {
+            return Err(Failure::malformed(
+                self.name().to_string(),
+                format!("box {} length {} is smaller than its header",
+                        box_type_to_string(&box_type), box_length),
+                offset));
+        }
+        return Ok(Some(BoxHeader {
+            box_type,
+            offset,
+            header_length,
+            body_length: box_length - header_length,
+        }));
+    }
+
+    fn quicktime_scan_for_box(&mut self, name: &[u8],
+                              uuid: Option<(u64, u64)>) -> Result<Option<Input<'f>>, Failure> {
+        while let Some(header) = self.quicktime_read_box_header()? {
+            let mut box_length = header.body_length;
+            if header.box_type == name {
                 match uuid {
-                    None => return Ok(self.section(box_length)),
+                    None => return Ok(Some(self.section(box_length))),
                     Some(u) => {
-                        let msb = self.read_u64(&Endianness::Big)?;
-                        let lsb = self.read_u64(&Endianness::Big)?;
+                        if box_length < 16 {
+                            return Err(Failure::malformed(
+                                self.name().to_string(),
+                                format!("uuid box length {} is too small for uuid", box_length),
+                                header.offset));
+                        }
+                        let msb = self.read_u64(&Endianness::Big)
+                            .map_err(|e| Failure::file_io(self.name().to_string(), "reading box uuid".to_string(), e))?;
+                        let lsb = self.read_u64(&Endianness::Big)
+                            .map_err(|e| Failure::file_io(self.name().to_string(), "reading box uuid".to_string(), e))?;
+                        box_length -= 16;
                         if u.0 == msb && u.1 == lsb {
-                            box_length = box_length - 16;
-                            return Ok(self.section(box_length));
+                            return Ok(Some(self.section(box_length)));
                         }
                     }
                 }
             }
-            self.ff(box_length)?;
+            self.ff(box_length)
+                .map_err(|e| Failure::file_io(
+                    self.name().to_string(),
+                    format!("fast-forward box {}", box_type_to_string(&header.box_type)),
+                    e))?;
         }
+        return Ok(None);
+    }
+
+    pub fn quicktime_search_box(&mut self, box_name: &str) -> Result<Option<Input<'_>>, Failure> {
+        return self.quicktime_scan_for_box(box_name.as_bytes(), None);
+    }
+
+    /// Same as `quicktime_search_box`, for box types that are not valid UTF-8, like ©mak.
+    pub fn quicktime_search_raw_box(&mut self, box_type: &[u8; 4]) -> Result<Option<Input<'_>>, Failure> {
+        return self.quicktime_scan_for_box(box_type, None);
     }
 
-    pub fn quicktime_search_box(&mut self, box_name: &str) -> io::Result<Input> {
-        return self.quicktime_scan_for_box(box_name, None);
+    pub fn quicktime_search_uuid_box(&mut self, box_uuid: (u64, u64)) -> Result<Option<Input<'_>>, Failure> {
+        return self.quicktime_scan_for_box(b"uuid", Some(box_uuid));
     }
 
-    pub fn quicktime_search_uuid_box(&mut self, box_uuid: (u64, u64)) -> io::Result<Input> {
-        return self.quicktime_scan_for_box(&"uuid", Some(box_uuid));
+    /// Lists all boxes in this input, descending into the well-known container boxes.
+    pub fn quicktime_box_tree(&mut self) -> Result<Vec<QuickTimeBox>, Failure> {
+        let mut boxes: Vec<QuickTimeBox> = Vec::new();
+        self.quicktime_collect_boxes(0, &mut boxes)?;
+        return Ok(boxes);
+    }
+
+    fn quicktime_collect_boxes(&mut self, depth: usize, boxes: &mut Vec<QuickTimeBox>) -> Result<(), Failure> {
+        while let Some(header) = self.quicktime_read_box_header()? {
+            boxes.push(QuickTimeBox {
+                box_type: box_type_to_string(&header.box_type),
+                offset: header.offset,
+                size: header.header_length + header.body_length,
+                depth,
+            });
+            if CONTAINER_BOXES.contains(&&header.box_type) {
+                let mut body = self.section(header.body_length);
+                // ISO meta box is a full box with version and flags preceding children,
+                // QuickTime meta box is not, telling them apart by zero version and flags:
+                if &header.box_type == b"meta" {
+                    let version_and_flags = body.read_u32(&Endianness::Big)
+                        .map_err(|e| Failure::file_io(self.name().to_string(), "reading meta box version".to_string(), e))?;
+                    if version_and_flags != 0 {
+                        body.seek(0)
+                            .map_err(|e| Failure::file_io(self.name().to_string(), "rewinding meta box".to_string(), e))?;
+                    }
+                }
+                // skipping the box below seeks from this input's cursor,
+                // so reading children does not need to be undone:
+                body.quicktime_collect_boxes(depth + 1, boxes)?;
+            }
+            self.ff(header.body_length)
+                .map_err(|e| Failure::file_io(
+                    self.name().to_string(),
+                    format!("fast-forward box {}", box_type_to_string(&header.box_type)),
+                    e))?;
+        }
+        return Ok(());
     }
 }
@@ -1,51 +1,170 @@
-use std::error::Error;
+use chrono::DateTime;
+use chrono::Duration;
+use chrono::FixedOffset;
+use chrono::NaiveDate;
+use chrono::NaiveDateTime;
+use chrono::Utc;
 
+use super::DatePriority;
 use super::Endianness;
+use super::ExifDateTag;
+use super::ExtractorOptions;
 use super::Failure;
 use super::FileMetadata;
+use super::Timestamp;
+use super::TimestampSource;
 use super::input::Input;
+use crate::timestampname::timezone::parse_utc_offset;
 
-struct TiffError {
-    description: String,
-    cause: String,
+// TIFF and EXIF IFDs:
+const TAG_MAKE: u16 = 0x010F;
+const TAG_MODEL: u16 = 0x0110;
+const TAG_DATE_TIME: u16 = 0x0132;
+const TAG_EXIF_IFD_POINTER: u16 = 0x8769;
+const TAG_GPS_IFD_POINTER: u16 = 0x8825;
+const TAG_DATE_TIME_ORIGINAL: u16 = 0x9003;
+const TAG_DATE_TIME_DIGITIZED: u16 = 0x9004;
+const TAG_OFFSET_TIME: u16 = 0x9010;
+const TAG_OFFSET_TIME_ORIGINAL: u16 = 0x9011;
+const TAG_OFFSET_TIME_DIGITIZED: u16 = 0x9012;
+const TAG_SUB_SEC_TIME: u16 = 0x9290;
+const TAG_SUB_SEC_TIME_ORIGINAL: u16 = 0x9291;
+const TAG_SUB_SEC_TIME_DIGITIZED: u16 = 0x9292;
+const TAG_BODY_SERIAL_NUMBER: u16 = 0xA431;
+const TAG_LENS_MODEL: u16 = 0xA434;
+// GPS IFD has its own tag numbering:
+const TAG_GPS_TIME_STAMP: u16 = 0x0007;
+const TAG_GPS_DATE_STAMP: u16 = 0x001D;
+
+// field types:
+const TYPE_ASCII: u16 = 2;
+const TYPE_LONG: u16 = 4;
+const TYPE_RATIONAL: u16 = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum IfdKind {
+    /// IFD0 and EXIF IFDs, sharing the same tag numbering.
+    Tiff,
+    Gps,
+}
+
+/// Tags relevant for timestamp extraction, collected from all IFDs of a TIFF structure.
+#[derive(Default)]
+pub struct TiffTags {
+    // date tag, value and absolute offset of the value:
+    dates: Vec<(u16, String, u64)>,
+    utc_offsets: Vec<(u16, String)>,
+    subsecs: Vec<(u16, String)>,
+    make: Option<String>,
+    model: Option<String>,
+    serial: Option<String>,
+    lens: Option<String>,
+    gps_date: Option<String>,
+    // hours, minutes and seconds as numerator and denominator pairs:
+    gps_time: Option<[(u32, u32); 3]>,
+}
+
+impl TiffTags {
+    /// Adds tags from another TIFF structure of the same file, present tags are kept.
+    pub fn merge(&mut self, other: TiffTags) {
+        self.dates.extend(other.dates);
+        self.utc_offsets.extend(other.utc_offsets);
+        self.subsecs.extend(other.subsecs);
+        self.make = self.make.take().or(other.make);
+        self.model = self.model.take().or(other.model);
+        self.serial = self.serial.take().or(other.serial);
+        self.lens = self.lens.take().or(other.lens);
+        self.gps_date = self.gps_date.take().or(other.gps_date);
+        self.gps_time = self.gps_time.take().or(other.gps_time);
+    }
 }
 
-fn tiff_err(description: String) -> TiffError {
-    return TiffError {
-        description,
-        cause: "".to_string(),
+pub fn tiff_extract_metadata_creation_timestamp(input: &mut Input, options: &ExtractorOptions) -> Result<Option<FileMetadata>, Failure> {
+    let tags = tiff_read_tags(input, IfdKind::Tiff)?;
+    return tiff_metadata_from_tags(input, &tags, options).map(Some);
+}
+
+pub fn tiff_metadata_from_tags(input: &Input, tags: &TiffTags, options: &ExtractorOptions) -> Result<FileMetadata, Failure> {
+    let file_name = input.name().to_string();
+    let chosen_date: Option<&(u16, String, u64)> = match &options.date_priority {
+        // first one of the equal dates is taken, which is the one closer to file start,
+        // blank or broken date tags are skipped, the file fails only if no date tag can be parsed:
+        DatePriority::Earliest => tags.dates.iter()
+            .filter_map(|d| parse_exif_date(&d.1).map(|date| (date, d)))
+            .min_by_key(|(date, _)| *date)
+            .map(|(_, d)| d)
+            .or_else(|| tags.dates.first()),
+        // blank tags, written by cameras that do not know the date, are skipped like with the earliest:
+        DatePriority::Ordered(priority) => priority.iter()
+            .find_map(|tag| tags.dates.iter().find(|d| date_tag_kind(d.0) == *tag && parse_exif_date(&d.1).is_some()))
+            .or_else(|| priority.iter().find_map(|tag| tags.dates.iter().find(|d| date_tag_kind(d.0) == *tag))),
     };
+    let (date_tag, date_value, date_offset) = match chosen_date {
+        Some(d) => d,
+        None if tags.dates.is_empty() => return Err(Failure::missing_timestamp(
+            file_name,
+            "TIFF no date tags were found".to_string())),
+        None => return Err(Failure::missing_timestamp(
+            file_name,
+            "TIFF no date tags allowed by priority were found".to_string()))
+    };
+    let date: NaiveDateTime = parse_exif_date(date_value)
+        .ok_or_else(|| Failure::malformed(
+            file_name.to_string(),
+            format!("invalid exif date format: {}", date_value),
+            *date_offset))?;
+    let subsec_millis: u32 = match_subsec_millis(*date_tag, &tags.dates, &tags.subsecs).unwrap_or(0);
+    return Ok(FileMetadata {
+        file_name,
+        timestamp: Timestamp::create(
+            date + Duration::milliseconds(subsec_millis as i64),
+            match_utc_offset(*date_tag, &tags.utc_offsets),
+            TimestampSource::Exif(date_tag_kind(*date_tag))),
+        camera_make: tags.make.clone(),
+        camera_model: tags.model.clone(),
+        camera_serial: tags.serial.clone(),
+        lens_model: tags.lens.clone(),
+        gps_timestamp: match_gps_timestamp(tags),
+        extension: format!(".{}", input.ext()),
+    });
 }
 
-fn tiff_err_cause<E: Error>(description: String, cause: E) -> TiffError {
-    return TiffError {
-        description,
-        cause: format!("{}", cause),
+fn date_tag_kind(tag: u16) -> ExifDateTag {
+    return match tag {
+        TAG_DATE_TIME_ORIGINAL => ExifDateTag::Original,
+        TAG_DATE_TIME_DIGITIZED => ExifDateTag::Digitized,
+        _ => ExifDateTag::Modified
     };
 }
 
-pub fn tiff_extract_metadata_creation_timestamp(input: &mut Input) -> Result<Option<FileMetadata>, Failure> {
-    return tiff_extract_metadata_creation_timestamp_impl(input)
-        .map(|t| {
-            Some(FileMetadata {
-                file_name: input.name().to_string(),
-                creation_timestamp: t,
-                extension: format!(".{}", input.ext().to_string()),
-            })
-        })
-        .map_err(|e| Failure::file_failure_strcause(
-            input.name().to_string(),
-            e.description,
-            e.cause));
+// ASCII values of up to 4 bytes, including terminating NULL, are stored in the field itself:
+fn inline_ascii(field_value: u32, count: u32, endianness: &Endianness) -> String {
+    let field_bytes: [u8; 4] = match endianness {
+        Endianness::Big => field_value.to_be_bytes(),
+        Endianness::Little => field_value.to_le_bytes()
+    };
+    return String::from_utf8_lossy(&field_bytes[..count as usize - 1]).to_string();
+}
+
+fn trim_ascii(value: &str) -> Option<String> {
+    let trimmed = value.trim_matches(|c: char| c == '\0' || c.is_whitespace());
+    return match trimmed.is_empty() {
+        true => None,
+        false => Some(trimmed.to_string())
+    };
 }
 
 // https://www.adobe.io/content/dam/udp/en/open/standards/tiff/TIFF6.pdf
-fn tiff_extract_metadata_creation_timestamp_impl(input: &mut Input) -> Result<String, TiffError> {
+// https://www.cipa.jp/std/documents/e/DC-008-2012_E.pdf
+pub fn tiff_read_tags(input: &mut Input, root_ifd: IfdKind) -> Result<TiffTags, Failure> {
+    let file_name = input.name().to_string();
     // Bytes 0-1: The byte order used within the file. Legal values are:
     // “II” (4949.H)
     // “MM” (4D4D.H)
+    let tiff_header_offset: u64 = input.position();
     let tiff_endianness_header: String = input.read_string(2)
-        .map_err(|e| tiff_err_cause(
+        .map_err(|e| Failure::file_io(
+            file_name.to_string(),
             "TIFF failed to read endianness header".to_string(), e))?;
     // In the “II” format, byte order is always from the least significant byte to the most
     // significant byte, for both 16-bit and 32-bit integers.
@@ -56,30 +175,41 @@ fn tiff_extract_metadata_creation_timestamp_impl(input: &mut Input) -> Result<St
     let endianness: Endianness = match tiff_endianness_header.as_str() {
         "II" => Ok(Endianness::Little),
         "MM" => Ok(Endianness::Big),
-        _ => Err(tiff_err(format!("invalid TIFF file header: {}", tiff_endianness_header)))
+        _ => Err(Failure::malformed(
+            file_name.to_string(),
+            format!("invalid TIFF file header: {}", tiff_endianness_header),
+            tiff_header_offset))
     }?;
 
     // Bytes 2-3 An arbitrary but carefully chosen number (42)
     // that further identifies the file as a TIFF file.
     let tiff_magic: u16 = input.read_u16(&endianness)
-        .map_err(|e| tiff_err_cause(
+        .map_err(|e| Failure::file_io(
+            file_name.to_string(),
             "TIFF failed to read magic number header".to_string(), e))?;
     if tiff_magic != 42 {
-        return Err(tiff_err(format!("invalid TIFF magic number: {}", tiff_magic)));
+        return Err(Failure::malformed(
+            file_name.to_string(),
+            format!("invalid TIFF magic number: {}", tiff_magic),
+            tiff_header_offset + 2));
     }
 
-    let mut ifd_offsets: Vec<u32> = Vec::new();
-    let mut date_tag_offsets: Vec<u32> = Vec::new();
+    let mut ifd_offsets: Vec<(u32, IfdKind)> = Vec::new();
+    // values which do not fit into the field: value offset, IFD kind, field tag and count:
+    let mut value_offsets: Vec<(u32, IfdKind, u16, u32)> = Vec::new();
+    // protection against IFDs pointing at each other:
+    let mut visited_ifd_offsets: Vec<u32> = Vec::new();
 
     // Bytes 4-7 The offset (in bytes) of the first IFD.
-    ifd_offsets.push(input.read_u32(&endianness)
-        .map_err(|e| tiff_err_cause(
-            "TIFF failed to read first IFD offset".to_string(), e))?);
+    ifd_offsets.push((input.read_u32(&endianness)
+        .map_err(|e| Failure::file_io(
+            file_name.to_string(),
+            "TIFF failed to read first IFD offset".to_string(), e))?, root_ifd));
 
-    let mut earliest_creation_date: String = String::new();
+    let mut tags = TiffTags::default();
 
     loop {
-        if ifd_offsets.is_empty() && date_tag_offsets.is_empty() {
+        if ifd_offsets.is_empty() && value_offsets.is_empty() {
             // TIFF no more offsets to scavenge
             break;
         }
@@ -87,144 +217,378 @@ fn tiff_extract_metadata_creation_timestamp_impl(input: &mut Input) -> Result<St
         // TODO should sorting happen here?
         // sorting to traverse file forward-only:
         ifd_offsets.sort_unstable();
-        date_tag_offsets.sort_unstable();
+        value_offsets.sort_unstable();
 
-        if !ifd_offsets.is_empty() || !date_tag_offsets.is_empty() {
+        if !ifd_offsets.is_empty() || !value_offsets.is_empty() {
             // TODO find a better way to solve this, maybe match?
-            let next_date_offset: u32 = match date_tag_offsets.len() {
-                x if x > 0 => date_tag_offsets[0],
-                _ => u32::max_value()
+            let (next_value_offset, next_value_ifd, next_value_tag, next_value_count): (u32, IfdKind, u16, u32) = match value_offsets.len() {
+                x if x > 0 => value_offsets[0],
+                _ => (u32::MAX, IfdKind::Tiff, 0, 0)
             };
-            let next_ifd_offset: u32 = match ifd_offsets.len() {
+            let (next_ifd_offset, next_ifd_kind): (u32, IfdKind) = match ifd_offsets.len() {
                 x if x > 0 => ifd_offsets[0],
-                _ => u32::max_value()
+                _ => (u32::MAX, IfdKind::Tiff)
             };
 
-            if next_date_offset < next_ifd_offset {
-                // TIFF collecting date at offset
-                date_tag_offsets.remove(0);
-                input.seek(next_date_offset as u64)
-                    .map_err(|e| tiff_err_cause(
-                        format!("TIFF failed to fast-forward to next date tag offset: {}", next_date_offset), e))?;
-                // reading 19 characters of string:
-                // yyyy-dd-mm HH:MM:SS
-                let date_tag: String = input.read_string(19)
-                    .map_err(|e| tiff_err_cause(
-                        format!("TIFF failed to read date tag at offset: {}", next_date_offset), e))?;
-                if earliest_creation_date.is_empty() {
-                    earliest_creation_date = date_tag;
-                } else {
-                    if date_tag < earliest_creation_date {
-                        earliest_creation_date = date_tag;
+            if next_value_offset < next_ifd_offset {
+                // TIFF collecting value at offset
+                value_offsets.remove(0);
+                input.seek(next_value_offset as u64)
+                    .map_err(|e| Failure::file_io(
+                        file_name.to_string(),
+                        format!("TIFF failed to fast-forward to next value offset: {}", next_value_offset), e))?;
+                let value_position: u64 = input.position();
+                if next_value_ifd == IfdKind::Gps && next_value_tag == TAG_GPS_TIME_STAMP {
+                    let mut rationals: [(u32, u32); 3] = [(0, 1); 3];
+                    for rational in rationals.iter_mut() {
+                        let numerator = input.read_u32(&endianness)
+                            .map_err(|e| Failure::file_io(
+                                file_name.to_string(),
+                                format!("TIFF failed to read GPS time stamp at offset: {}", next_value_offset), e))?;
+                        let denominator = input.read_u32(&endianness)
+                            .map_err(|e| Failure::file_io(
+                                file_name.to_string(),
+                                format!("TIFF failed to read GPS time stamp at offset: {}", next_value_offset), e))?;
+                        *rational = (numerator, denominator);
+                    }
+                    tags.gps_time = Some(rationals);
+                    continue;
+                }
+                // count includes terminating NULL,
+                // values other than dates are free text and should not fail the file:
+                let value_bytes: Vec<u8> = input.read_bytes(next_value_count as u64 - 1)
+                    .map_err(|e| Failure::file_io(
+                        file_name.to_string(),
+                        format!("TIFF failed to read value of tag {} at offset: {}", next_value_tag, next_value_offset), e))?;
+                let value: String = String::from_utf8_lossy(&value_bytes).to_string();
+                match (next_value_ifd, next_value_tag) {
+                    (IfdKind::Gps, _) => tags.gps_date = Some(value),
+                    (_, TAG_MAKE) => tags.make = trim_ascii(&value),
+                    (_, TAG_MODEL) => tags.model = trim_ascii(&value),
+                    (_, TAG_BODY_SERIAL_NUMBER) => tags.serial = trim_ascii(&value),
+                    (_, TAG_LENS_MODEL) => tags.lens = trim_ascii(&value),
+                    (_, TAG_OFFSET_TIME) | (_, TAG_OFFSET_TIME_ORIGINAL) | (_, TAG_OFFSET_TIME_DIGITIZED) => {
+                        tags.utc_offsets.push((next_value_tag, value));
+                    }
+                    (_, TAG_SUB_SEC_TIME) | (_, TAG_SUB_SEC_TIME_ORIGINAL) | (_, TAG_SUB_SEC_TIME_DIGITIZED) => {
+                        tags.subsecs.push((next_value_tag, value));
+                    }
+                    _ => {
+                        // yyyy-dd-mm HH:MM:SS
+                        tags.dates.push((next_value_tag, value, value_position));
                     }
                 }
             } else {
                 // TIFF scavenging IFD at offset
                 ifd_offsets.remove(0);
+                if visited_ifd_offsets.contains(&next_ifd_offset) {
+                    continue;
+                }
+                visited_ifd_offsets.push(next_ifd_offset);
                 input.seek(next_ifd_offset as u64)
-                    .map_err(|e| tiff_err_cause(
+                    .map_err(|e| Failure::file_io(
+                        file_name.to_string(),
                         format!("TIFF failed to fast-forward to next IFD offset: {}", next_ifd_offset), e))?;
 
                 // 2-byte count of the number of directory entries (i.e., the number of fields)
                 let fields = input.read_u16(&endianness)
-                    .map_err(|e| tiff_err_cause(
+                    .map_err(|e| Failure::file_io(
+                        file_name.to_string(),
                         format!("TIFF failed to read IFD field count at offset: {}", next_ifd_offset), e))?;
                 let mut i: u16 = 0;
                 while i < fields {
+                    let field_offset: u64 = input.position();
 
                     // Bytes 0-1 The Tag that identifies the field
                     let field_tag = input.read_u16(&endianness)
-                        .map_err(|e| tiff_err_cause(
+                        .map_err(|e| Failure::file_io(
+                            file_name.to_string(),
                             format!("TIFF failed to read field tag for field: {}", i), e))?;
 
                     // Bytes 2-3 The field Type
                     let field_type = input.read_u16(&endianness)
-                        .map_err(|e| tiff_err_cause(
+                        .map_err(|e| Failure::file_io(
+                            file_name.to_string(),
                             format!("TIFF failed to read field type for field: {}", i), e))?;
 
                     // Bytes 4-7 The number of values, Count of the indicated Type
                     let field_count = input.read_u32(&endianness)
-                        .map_err(|e| tiff_err_cause(
+                        .map_err(|e| Failure::file_io(
+                            file_name.to_string(),
                             format!("TIFF failed to read field count for field: {}", i), e))?;
 
                     // Bytes 8-11 The Value Offset, the file offset (in bytes) of the Value for the field
                     let field_value_offset = input.read_u32(&endianness)
-                        .map_err(|e| tiff_err_cause(
+                        .map_err(|e| Failure::file_io(
+                            file_name.to_string(),
                             format!("TIFF failed to read field value offset for field: {}", i), e))?;
 
+                    i += 1;
+
+                    if next_ifd_kind == IfdKind::Gps {
+                        // optional tags, malformed ones are ignored instead of failing the file:
+                        // 0x001D: GPSDateStamp, YYYY:MM:DD
+                        if field_tag == TAG_GPS_DATE_STAMP && field_type == TYPE_ASCII && field_count == 11 {
+                            value_offsets.push((field_value_offset, IfdKind::Gps, field_tag, field_count));
+                        }
+                        // 0x0007: GPSTimeStamp, 3 rationals of hours, minutes and seconds
+                        if field_tag == TAG_GPS_TIME_STAMP && field_type == TYPE_RATIONAL && field_count == 3 {
+                            value_offsets.push((field_value_offset, IfdKind::Gps, field_tag, field_count));
+                        }
+                        continue;
+                    }
+
                     // 0x0132: DateTime
                     // 0x9003: DateTimeOriginal
                     // 0x9004: DateTimeDigitized
-                    if field_tag == 0x0132 || field_tag == 0x9003 || field_tag == 0x9004 {
-                        if field_type != 2 {
-                            return Err(tiff_err(format!("expected tag has unexpected type: {} == {}", field_tag, field_type)));
+                    if field_tag == TAG_DATE_TIME || field_tag == TAG_DATE_TIME_ORIGINAL || field_tag == TAG_DATE_TIME_DIGITIZED {
+                        if field_type != TYPE_ASCII {
+                            return Err(Failure::malformed(
+                                file_name.to_string(),
+                                format!("expected tag has unexpected type: {} == {}", field_tag, field_type),
+                                field_offset));
                         }
                         if field_count != 20 {
-                            return Err(tiff_err(format!("expected tag has unexpected count: {} == {}", field_tag, field_count)));
+                            return Err(Failure::malformed(
+                                file_name.to_string(),
+                                format!("expected tag has unexpected count: {} == {}", field_tag, field_count),
+                                field_offset));
+                        }
+                        value_offsets.push((field_value_offset, IfdKind::Tiff, field_tag, field_count));
+                    }
+                    // 0x9010: OffsetTime
+                    // 0x9011: OffsetTimeOriginal
+                    // 0x9012: OffsetTimeDigitized
+                    // optional tags, malformed ones are ignored instead of failing the file,
+                    // 7 characters including NULL do not fit into the field and are stored at offset:
+                    if (field_tag == TAG_OFFSET_TIME || field_tag == TAG_OFFSET_TIME_ORIGINAL || field_tag == TAG_OFFSET_TIME_DIGITIZED)
+                        && field_type == TYPE_ASCII
+                        && field_count == 7 {
+                        value_offsets.push((field_value_offset, IfdKind::Tiff, field_tag, field_count));
+                    }
+                    // 0x9290: SubSecTime
+                    // 0x9291: SubSecTimeOriginal
+                    // 0x9292: SubSecTimeDigitized
+                    // 0x010F: Make
+                    // 0x0110: Model
+                    // 0xA431: BodySerialNumber
+                    // 0xA434: LensModel
+                    // optional tags as well, subseconds are usually stored in the field itself:
+                    if (field_tag == TAG_SUB_SEC_TIME || field_tag == TAG_SUB_SEC_TIME_ORIGINAL || field_tag == TAG_SUB_SEC_TIME_DIGITIZED
+                        || field_tag == TAG_MAKE || field_tag == TAG_MODEL || field_tag == TAG_BODY_SERIAL_NUMBER
+                        || field_tag == TAG_LENS_MODEL)
+                        && field_type == TYPE_ASCII
+                        && field_count > 1 {
+                        if field_count <= 4 {
+                            let value: String = inline_ascii(field_value_offset, field_count, &endianness);
+                            match field_tag {
+                                TAG_MAKE => tags.make = trim_ascii(&value),
+                                TAG_MODEL => tags.model = trim_ascii(&value),
+                                TAG_BODY_SERIAL_NUMBER => tags.serial = trim_ascii(&value),
+                                TAG_LENS_MODEL => tags.lens = trim_ascii(&value),
+                                _ => tags.subsecs.push((field_tag, value))
+                            }
+                        } else {
+                            value_offsets.push((field_value_offset, IfdKind::Tiff, field_tag, field_count));
                         }
-                        date_tag_offsets.push(field_value_offset);
                     }
                     // 0x8769: ExifIFDPointer
-                    if field_tag == 0x8769 {
-                        if field_type != 4 {
-                            return Err(tiff_err(format!("EXIF pointer tag has unexpected type: {} == {}", field_tag, field_type)));
+                    if field_tag == TAG_EXIF_IFD_POINTER {
+                        if field_type != TYPE_LONG {
+                            return Err(Failure::malformed(
+                                file_name.to_string(),
+                                format!("EXIF pointer tag has unexpected type: {} == {}", field_tag, field_type),
+                                field_offset));
                         }
                         if field_count != 1 {
-                            return Err(tiff_err(format!("EXIF pointer tag has unexpected size: {} == {}", field_tag, field_count)));
+                            return Err(Failure::malformed(
+                                file_name.to_string(),
+                                format!("EXIF pointer tag has unexpected size: {} == {}", field_tag, field_count),
+                                field_offset));
                         }
-                        ifd_offsets.push(field_value_offset);
+                        ifd_offsets.push((field_value_offset, IfdKind::Tiff));
+                    }
+                    // 0x8825: GPSInfoIFDPointer, optional as well
+                    if field_tag == TAG_GPS_IFD_POINTER && field_type == TYPE_LONG && field_count == 1 {
+                        ifd_offsets.push((field_value_offset, IfdKind::Gps));
                     }
-                    i = i + 1;
                 }
 
                 // followed by a 4-byte offset of the next IFD (or 0 if none).
                 // (Do not forget to write the 4 bytes of 0 after the last IFD.)
                 let next_ifd_offset = input.read_u32(&endianness)
-                    .map_err(|e| tiff_err_cause(
-                        format!("TIFF failed to read next IFD offset"), e))?;
+                    .map_err(|e| Failure::file_io(
+                        file_name.to_string(),
+                        "TIFF failed to read next IFD offset".to_string(), e))?;
                 if next_ifd_offset != 0 {
-                    ifd_offsets.push(next_ifd_offset);
+                    ifd_offsets.push((next_ifd_offset, next_ifd_kind));
                 }
             }
         }
     }
 
-    if earliest_creation_date.is_empty() {
-        return Err(tiff_err("TIFF no date tags were found".to_string()));
+    return Ok(tags);
+}
+
+// GPS time is UTC, seconds may be fractional:
+fn match_gps_timestamp(tags: &TiffTags) -> Option<DateTime<Utc>> {
+    let gps_date: &str = tags.gps_date.as_deref()?;
+    let gps_time: [(u32, u32); 3] = tags.gps_time?;
+    let date = NaiveDate::parse_from_str(gps_date.trim_end_matches('\0'), "%Y:%m:%d").ok()?;
+    if gps_time.iter().any(|(_, denominator)| *denominator == 0) {
+        return None;
+    }
+    let hours: u32 = gps_time[0].0 / gps_time[0].1;
+    let minutes: u32 = gps_time[1].0 / gps_time[1].1;
+    let millis: u64 = gps_time[2].0 as u64 * 1000 / gps_time[2].1 as u64;
+    let time = date.and_hms_milli_opt(hours, minutes, (millis / 1000) as u32, (millis % 1000) as u32)?;
+    return Some(time.and_utc());
+}
+
+// subseconds tag describing the chosen date tag is preferred,
+// subseconds of other date tags are only applicable if dates are equal:
+fn match_subsec_millis(date_tag: u16, dates: &[(u16, String, u64)], subsecs: &[(u16, String)]) -> Option<u32> {
+    let subsec_tag_of = |tag: u16| match tag {
+        TAG_DATE_TIME_ORIGINAL => TAG_SUB_SEC_TIME_ORIGINAL,
+        TAG_DATE_TIME_DIGITIZED => TAG_SUB_SEC_TIME_DIGITIZED,
+        _ => TAG_SUB_SEC_TIME
+    };
+    let chosen_date: &str = dates.iter()
+        .find(|(t, _, _)| *t == date_tag)
+        .map(|(_, d, _)| d.as_str())?;
+    let applicable_tags: Vec<u16> = std::iter::once(date_tag)
+        .chain(dates.iter()
+            .filter(|(t, d, _)| *t != date_tag && d == chosen_date)
+            .map(|(t, _, _)| *t))
+        .map(subsec_tag_of)
+        .collect();
+    return applicable_tags.iter()
+        .flat_map(|tag| subsecs.iter().filter(move |(t, _)| t == tag))
+        .find_map(|(_, value)| parse_subsec_millis(value));
+}
+
+// subseconds are stored as decimal fraction digits: "5" is 500ms, "12345" is 123ms:
+fn parse_subsec_millis(value: &str) -> Option<u32> {
+    let digits: &str = value.trim_end_matches([' ', '\0']);
+    if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
     }
-    return match_and_format_exif_date(&mut earliest_creation_date);
+    let millis: String = digits.chars().chain("00".chars()).take(3).collect();
+    return millis.parse().ok();
 }
 
-fn match_and_format_exif_date(exif_date: &mut String) -> Result<String, TiffError> {
+// offset tag describing the chosen date tag is preferred,
+// otherwise the camera zone is assumed to be the same for all date tags:
+fn match_utc_offset(date_tag: u16, utc_offsets: &[(u16, String)]) -> Option<FixedOffset> {
+    let matching_offset_tag: u16 = match date_tag {
+        TAG_DATE_TIME_ORIGINAL => TAG_OFFSET_TIME_ORIGINAL,
+        TAG_DATE_TIME_DIGITIZED => TAG_OFFSET_TIME_DIGITIZED,
+        _ => TAG_OFFSET_TIME
+    };
+    let preference: [u16; 4] = [
+        matching_offset_tag,
+        TAG_OFFSET_TIME_ORIGINAL,
+        TAG_OFFSET_TIME_DIGITIZED,
+        TAG_OFFSET_TIME];
+    return preference.iter()
+        .flat_map(|tag| utc_offsets.iter().filter(move |(t, _)| t == tag))
+        // unknown offset is written as blanks, which is not parsed:
+        .find_map(|(_, value)| parse_utc_offset(value));
+}
+
+fn parse_exif_date(exif_date: &str) -> Option<NaiveDateTime> {
     let chars: Vec<char> = exif_date.chars().collect();
-    if chars[0].is_digit(10)
-        && chars[1].is_digit(10)
-        && chars[2].is_digit(10)
-        && chars[3].is_digit(10)
+    if chars.len() == 19
+        && chars[0].is_ascii_digit()
+        && chars[1].is_ascii_digit()
+        && chars[2].is_ascii_digit()
+        && chars[3].is_ascii_digit()
         // due to Samsung bug, have to check for both : and -
         && (chars[4] == ':' || chars[4] == '-')
-        && chars[5].is_digit(10)
-        && chars[6].is_digit(10)
+        && chars[5].is_ascii_digit()
+        && chars[6].is_ascii_digit()
         // due to Samsung bug, have to check for both : and -
         && (chars[7] == ':' || chars[7] == '-')
-        && chars[8].is_digit(10)
-        && chars[9].is_digit(10)
+        && chars[8].is_ascii_digit()
+        && chars[9].is_ascii_digit()
         && chars[10] == ' '
-        && chars[11].is_digit(10)
-        && chars[12].is_digit(10)
+        && chars[11].is_ascii_digit()
+        && chars[12].is_ascii_digit()
         && chars[13] == ':'
-        && chars[14].is_digit(10)
-        && chars[15].is_digit(10)
+        && chars[14].is_ascii_digit()
+        && chars[15].is_ascii_digit()
         && chars[16] == ':'
-        && chars[17].is_digit(10)
-        && chars[18].is_digit(10) {
-        exif_date.remove(16);
-        exif_date.remove(13);
-        exif_date.remove(10);
-        exif_date.remove(7);
-        exif_date.remove(4);
-        exif_date.insert(8, '-');
-        return Ok(exif_date.to_string());
+        && chars[17].is_ascii_digit()
+        && chars[18].is_ascii_digit() {
+        let normalized: String = exif_date.replace('-', ":");
+        // calendar validity is checked here, months and days out of range are not parsed:
+        return NaiveDateTime::parse_from_str(&normalized, "%Y:%m:%d %H:%M:%S").ok();
+    }
+    return None;
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::*;
+    use super::super::InputBox;
+    use crate::timestampname::failures::FailureKind;
+
+    // little-endian TIFF with a single IFD of ASCII tags, values follow the IFD:
+    fn tiff(fields: &[(u16, &str)]) -> Vec<u8> {
+        let mut bytes: Vec<u8> = b"II".to_vec();
+        bytes.extend_from_slice(&42u16.to_le_bytes());
+        bytes.extend_from_slice(&8u32.to_le_bytes());
+        bytes.extend_from_slice(&(fields.len() as u16).to_le_bytes());
+        let mut values: Vec<u8> = Vec::new();
+        let values_offset: usize = 8 + 2 + fields.len() * 12 + 4;
+        for (tag, value) in fields {
+            bytes.extend_from_slice(&tag.to_le_bytes());
+            bytes.extend_from_slice(&TYPE_ASCII.to_le_bytes());
+            bytes.extend_from_slice(&(value.len() as u32 + 1).to_le_bytes());
+            bytes.extend_from_slice(&((values_offset + values.len()) as u32).to_le_bytes());
+            values.extend_from_slice(value.as_bytes());
+            values.push(0);
+        }
+        bytes.extend_from_slice(&0u32.to_le_bytes());
+        bytes.extend_from_slice(&values);
+        return bytes;
+    }
+
+    fn options(date_priority: DatePriority) -> ExtractorOptions {
+        return ExtractorOptions {
+            mp4_default_clock: super::super::Mp4Clock::Local,
+            mp4_clock_rules: Vec::new(),
+            date_priority,
+            fallbacks: Vec::new(),
+            file_name_patterns: Vec::new(),
+            include_unsupported: false,
+        };
+    }
+
+    fn extract(name: &str, bytes: &[u8], date_priority: DatePriority) -> Result<Option<FileMetadata>, Failure> {
+        let file = InputBox::from_bytes(name, "nef", bytes);
+        return tiff_extract_metadata_creation_timestamp(&mut Input::create(&file), &options(date_priority));
+    }
+
+    #[test]
+    fn blank_date_tag_is_skipped() {
+        // date unknown to the camera is written as spaces:
+        let file = tiff(&[(TAG_DATE_TIME, "2020:05:17 11:00:00"), (TAG_DATE_TIME_ORIGINAL, &" ".repeat(19))]);
+        let expected = NaiveDate::from_ymd_opt(2020, 5, 17).unwrap().and_hms_opt(11, 0, 0).unwrap();
+        for priority in [DatePriority::default(), DatePriority::Earliest] {
+            let timestamp = extract("blank-original", &file, priority).unwrap().unwrap().timestamp;
+            assert_eq!(timestamp.local, expected);
+            assert_eq!(timestamp.source, TimestampSource::Exif(ExifDateTag::Modified));
+        }
+    }
+
+    #[test]
+    fn only_blank_date_tags_are_malformed() {
+        let file = tiff(&[(TAG_DATE_TIME_ORIGINAL, &" ".repeat(19))]);
+        let failure = extract("blank-only", &file, DatePriority::default()).err().unwrap();
+        // offset of the value, after the header and the IFD with a single field:
+        assert_eq!(failure.kind(), FailureKind::Malformed { offset: 8 + 2 + 12 + 4 });
     }
-    return Err(tiff_err(format!("invalid exif date format: {}", exif_date)));
 }
@@ -1,83 +1,141 @@
 use std::error::Error;
 use std::fmt;
 use std::fmt::Formatter;
+use std::io;
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FailureKind {
+    /// Operating system level failure: opening, reading, listing, renaming.
+    Io,
+    /// Input ended before the structure being read was complete,
+    /// offset is absolute within the file.
+    Truncated { offset: u64 },
+    /// File format or one of its variants is not supported.
+    Unsupported,
+    /// File structure is invalid, offset is absolute within the file.
+    Malformed { offset: u64 },
+    /// File structure is valid, but no creation timestamp is stored in it.
+    MissingTimestamp,
+    /// Planned operations would overwrite each other or existing files.
+    Conflict,
+}
+
+impl fmt::Display for FailureKind {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        match self {
+            FailureKind::Io => write!(f, "I/O error"),
+            FailureKind::Truncated { offset } => write!(f, "truncated input at offset {}", offset),
+            FailureKind::Unsupported => write!(f, "unsupported format"),
+            FailureKind::Malformed { offset } => write!(f, "malformed structure at offset {}", offset),
+            FailureKind::MissingTimestamp => write!(f, "missing timestamp"),
+            FailureKind::Conflict => write!(f, "conflict"),
+        }
+    }
+}
+
+/// Cause of the `UnexpectedEof` errors produced by bounds checks on file inputs.
 #[derive(Debug)]
-enum FailureType {
-    File {
-        file_name: String,
-        description: String,
-        cause: String,
-    },
-    Env {
-        operation: String,
-        cause: String,
-    },
+pub struct TruncatedRead {
+    /// Absolute offset within the file at which the read was attempted.
+    pub offset: u64,
+    pub requested: u64,
+    pub available: u64,
+}
+
+impl Error for TruncatedRead {}
+
+impl fmt::Display for TruncatedRead {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        return write!(f, "EOF: reading {} bytes at offset {}, {} bytes available",
+                      self.requested, self.offset, self.available);
+    }
 }
 
 #[derive(Debug)]
 pub struct Failure {
-    fail_type: FailureType
+    kind: FailureKind,
+    // absent for failures not related to a particular file:
+    file_name: Option<String>,
+    description: String,
+    source: Option<Box<dyn Error + Send + Sync>>,
 }
 
-impl std::error::Error for Failure {}
+impl Error for Failure {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        return self.source.as_ref().map(|s| s.as_ref() as &(dyn Error + 'static));
+    }
+}
 
 impl fmt::Display for Failure {
     fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
-        match &self.fail_type {
-            FailureType::File { file_name, description, cause } => {
-                write!(f, "\tFile: {}\n\tDescription: {}{}", *file_name, *description, *cause)?;
-            }
-            FailureType::Env { operation, cause } => {
-                write!(f, "\tOperation: {}{}", *operation, *cause)?;
-            }
-        };
+        match &self.file_name {
+            Some(file_name) => write!(f, "\tFile: {}\n\tDescription: {}", file_name, self.description)?,
+            None => write!(f, "\tOperation: {}", self.description)?,
+        }
+        if let FailureKind::Malformed { offset } | FailureKind::Truncated { offset } = self.kind {
+            write!(f, "\n\tOffset: {}", offset)?;
+        }
+        if let Some(source) = &self.source {
+            write!(f, "\n\tCause: {}", source)?;
+        }
         return Ok(());
     }
 }
 
 impl Failure {
-    pub fn env_failure_caused<E: Error>(operation: String, cause: E) -> Failure {
-        Failure {
-            fail_type: FailureType::Env {
-                operation,
-                cause: format!("\n\tCause: {}", cause),
-            }
-        }
+    fn create(kind: FailureKind,
+              file_name: Option<String>,
+              description: String,
+              source: Option<Box<dyn Error + Send + Sync>>) -> Failure {
+        return Failure {
+            kind,
+            file_name,
+            description,
+            source,
+        };
     }
 
-    pub fn file_failure(file_name: String,
-                        description: String) -> Failure {
-        Failure {
-            fail_type: FailureType::File {
-                file_name,
-                description,
-                cause: "".to_string(),
-            }
-        }
+    pub fn env_io(operation: String, cause: io::Error) -> Failure {
+        return Failure::create(FailureKind::Io, None, operation, Some(Box::new(cause)));
     }
 
-    pub fn file_failure_caused<E: Error>(file_name: String,
-                                         description: String,
-                                         cause: E) -> Failure {
-        Failure {
-            fail_type: FailureType::File {
-                file_name,
-                description,
-                cause: format!("\n\tCause: {}", cause),
-            }
-        }
+    pub fn file_io(file_name: String, description: String, cause: io::Error) -> Failure {
+        let kind = match cause.get_ref().and_then(|e| e.downcast_ref::<TruncatedRead>()) {
+            Some(truncated) => FailureKind::Truncated { offset: truncated.offset },
+            None => FailureKind::Io,
+        };
+        return Failure::create(kind, Some(file_name), description, Some(Box::new(cause)));
     }
 
-    pub fn file_failure_strcause(file_name: String,
-                                 description: String,
-                                 cause: String) -> Failure {
-        Failure {
-            fail_type: FailureType::File {
-                file_name,
-                description,
-                cause
-            }
-        }
+    pub fn unsupported(file_name: String, description: String) -> Failure {
+        return Failure::create(FailureKind::Unsupported, Some(file_name), description, None);
+    }
+
+    pub fn malformed(file_name: String, description: String, offset: u64) -> Failure {
+        return Failure::create(FailureKind::Malformed { offset }, Some(file_name), description, None);
+    }
+
+    pub fn missing_timestamp(file_name: String, description: String) -> Failure {
+        return Failure::create(FailureKind::MissingTimestamp, Some(file_name), description, None);
+    }
+
+    pub fn conflict(file_name: String, description: String) -> Failure {
+        return Failure::create(FailureKind::Conflict, Some(file_name), description, None);
+    }
+
+    pub fn kind(&self) -> FailureKind {
+        return self.kind;
+    }
+
+    pub fn file_name(&self) -> Option<&str> {
+        return self.file_name.as_deref();
+    }
+
+    /// Single-line description of the failure, used for summary listings.
+    pub fn reason(&self) -> String {
+        return match &self.source {
+            Some(source) => format!("{}: {}: {}", self.kind, self.description, source),
+            None => format!("{}: {}", self.kind, self.description),
+        };
     }
 }
@@ -0,0 +1,109 @@
+use chrono::Local;
+use chrono::NaiveDate;
+use chrono::TimeZone;
+use chrono::Utc;
+use regex::Captures;
+use regex::Regex;
+
+use super::TimestampSource;
+use super::timestamp::Timestamp;
+
+// date groups every pattern must have, time groups default to zero:
+const REQUIRED_GROUPS: [&str; 3] = ["year", "month", "day"];
+const KNOWN_GROUPS: [&str; 7] = ["year", "month", "day", "hour", "minute", "second", "millis"];
+
+// patterns and whether they encode UTC, wall clock time of unknown zone otherwise:
+const BUILT_IN_PATTERNS: [(&str, bool); 5] = [
+    // Pixel: PXL_20230102_101112345.jpg
+    (r"^PXL_(?P<year>\d{4})(?P<month>\d{2})(?P<day>\d{2})_(?P<hour>\d{2})(?P<minute>\d{2})(?P<second>\d{2})(?P<millis>\d{3})", true),
+    // Android camera: 20180430_184327.jpg, IMG_20180430_184327.jpg, VID_20180430_184327.mp4
+    (r"^(?:IMG_|VID_)?(?P<year>\d{4})(?P<month>\d{2})(?P<day>\d{2})_(?P<hour>\d{2})(?P<minute>\d{2})(?P<second>\d{2})", false),
+    // WhatsApp, date only: IMG-20180430-WA0001.jpg
+    (r"^(?:IMG|VID)-(?P<year>\d{4})(?P<month>\d{2})(?P<day>\d{2})-WA\d+", false),
+    // Android screenshots: Screenshot_20180430-184327.jpg, Screenshot_2023-01-02-10-11-12-345_com.app.jpg
+    (r"^Screenshot_(?P<year>\d{4})-?(?P<month>\d{2})-?(?P<day>\d{2})[-_](?P<hour>\d{2})-?(?P<minute>\d{2})-?(?P<second>\d{2})(?:-(?P<millis>\d{3}))?", false),
+    // macOS screenshots: Screenshot 2023-01-02 at 10.11.12.jpg
+    (r"^Screen ?[Ss]hot (?P<year>\d{4})-(?P<month>\d{2})-(?P<day>\d{2}) at (?P<hour>\d{1,2})\.(?P<minute>\d{2})\.(?P<second>\d{2})", false),
+];
+
+/// Regular expression with named groups for date and time fields.
+pub struct FileNamePattern {
+    regex: Regex,
+    /// Whether the name has UTC time, converted into the local time zone then.
+    utc: bool,
+}
+
+/// Compiles a pattern with `year`, `month` and `day` named groups, optionally
+/// `hour`, `minute`, `second` and `millis`, returns a description of the problem otherwise.
+/// Names are taken as wall clock time of unknown zone.
+pub fn parse_file_name_pattern(value: &str) -> Result<FileNamePattern, String> {
+    let regex = Regex::new(value).map_err(|e| e.to_string())?;
+    let names: Vec<&str> = regex.capture_names().flatten().collect();
+    if let Some(missing) = REQUIRED_GROUPS.iter().find(|g| !names.contains(g)) {
+        return Err(format!("named group '{}' is required", missing));
+    }
+    if let Some(unknown) = names.iter().find(|n| !KNOWN_GROUPS.contains(n)) {
+        return Err(format!("named group '{}' is not one of {}", unknown, KNOWN_GROUPS.join(", ")));
+    }
+    return Ok(FileNamePattern { regex, utc: false });
+}
+
+pub fn built_in_file_name_patterns() -> Vec<FileNamePattern> {
+    return BUILT_IN_PATTERNS.iter()
+        .map(|(p, utc)| FileNamePattern {
+            utc: *utc,
+            ..parse_file_name_pattern(p).expect("built-in file name pattern is invalid")
+        })
+        .collect();
+}
+
+fn group_value(captures: &Captures, name: &str) -> Option<u32> {
+    return match captures.name(name) {
+        Some(m) => m.as_str().parse().ok(),
+        None => Some(0)
+    };
+}
+
+/// Timestamp encoded in the file name by the first matching pattern.
+pub fn timestamp_from_file_name(file_name: &str, patterns: &[FileNamePattern]) -> Option<Timestamp> {
+    return patterns.iter()
+        .filter_map(|p| p.regex.captures(file_name).map(|c| (p, c)))
+        // names like 00000000_000000.jpg match, but are not dates:
+        .find_map(|(p, c)| {
+            let local = NaiveDate::from_ymd_opt(
+                group_value(&c, "year")? as i32,
+                group_value(&c, "month")?,
+                group_value(&c, "day")?)?
+                .and_hms_milli_opt(
+                    group_value(&c, "hour")?,
+                    group_value(&c, "minute")?,
+                    group_value(&c, "second")?,
+                    group_value(&c, "millis")?)?;
+            return match p.utc {
+                true => Some(Timestamp::zoned(
+                    Utc.from_utc_datetime(&local).with_timezone(&Local).fixed_offset(),
+                    TimestampSource::FileName)),
+                false => Some(Timestamp::create(local, None, TimestampSource::FileName))
+            };
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pixel_names_are_utc() {
+        let timestamp = timestamp_from_file_name("PXL_20230702_101112345.jpg", &built_in_file_name_patterns()).unwrap();
+        let expected = NaiveDate::from_ymd_opt(2023, 7, 2).unwrap().and_hms_milli_opt(10, 11, 12, 345).unwrap();
+        assert_eq!(timestamp.instant().unwrap().naive_utc(), expected);
+    }
+
+    #[test]
+    fn android_names_are_wall_clock_times() {
+        let timestamp = timestamp_from_file_name("IMG_20180430_184327.jpg", &built_in_file_name_patterns()).unwrap();
+        let expected = NaiveDate::from_ymd_opt(2018, 4, 30).unwrap().and_hms_opt(18, 43, 27).unwrap();
+        assert_eq!(timestamp.local, expected);
+        assert_eq!(timestamp.offset, None);
+    }
+}
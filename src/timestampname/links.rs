@@ -0,0 +1,153 @@
+use std::collections::HashSet;
+use std::ffi::OsStr;
+use std::fs;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+use std::path::PathBuf;
+
+use super::RenameOperation;
+use super::Transfer;
+use super::failures::Failure;
+
+// hidden, so that it is not renamed along with the links:
+const MANIFEST_NAME: &str = ".timestampname-links";
+
+/// Link created in a destination, recorded so that pruning never touches other files.
+pub struct LinkRecord {
+    /// Path of the link relative to the destination.
+    link: PathBuf,
+    /// Absolute path of the source the link was made for.
+    source: PathBuf,
+    /// Inode of the source when the link was made, hard links share it.
+    inode: u64,
+}
+
+impl LinkRecord {
+    /// Whether the link is still the one that was made, not replaced by another file.
+    fn is_intact(&self, destination: &Path, transfer: Transfer) -> bool {
+        let link: PathBuf = destination.join(&self.link);
+        return match transfer {
+            Transfer::HardLink => fs::symlink_metadata(&link)
+                .is_ok_and(|m| m.file_type().is_file() && m.ino() == self.inode),
+            Transfer::SymbolicLink => fs::read_link(&link).is_ok_and(|target| target == self.source),
+            Transfer::Move | Transfer::Copy => false,
+        };
+    }
+
+    /// Whether the source was removed or replaced by another file.
+    fn source_gone(&self) -> bool {
+        return !fs::metadata(&self.source).is_ok_and(|m| m.ino() == self.inode);
+    }
+}
+
+fn manifest_io(destination: &Path, e: io::Error) -> Failure {
+    return Failure::env_io(format!("Failed to access {}", destination.join(MANIFEST_NAME).display()), e);
+}
+
+/// Links recorded by previous runs, none if the destination has no record yet.
+/// Records are the link, the source and the inode, each followed by a NUL character.
+pub fn read_link_records(destination: &Path) -> Result<Vec<LinkRecord>, Failure> {
+    let content: Vec<u8> = match fs::read(destination.join(MANIFEST_NAME)) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(manifest_io(destination, e))
+    };
+    let fields: Vec<&[u8]> = match content.strip_suffix(&[0]) {
+        Some(fields) => fields.split(|b| *b == 0).collect(),
+        None if content.is_empty() => Vec::new(),
+        None => return Err(manifest_io(destination, io::Error::new(io::ErrorKind::InvalidData, "incomplete link record")))
+    };
+    if !fields.len().is_multiple_of(3) {
+        return Err(manifest_io(destination, io::Error::new(io::ErrorKind::InvalidData, "incomplete link record")));
+    }
+    let mut records: Vec<LinkRecord> = Vec::new();
+    for record in fields.chunks(3) {
+        let inode: u64 = std::str::from_utf8(record[2]).ok()
+            .and_then(|i| i.parse::<u64>().ok())
+            .ok_or_else(|| manifest_io(destination, io::Error::new(io::ErrorKind::InvalidData, "invalid inode of link record")))?;
+        records.push(LinkRecord {
+            link: PathBuf::from(OsStr::from_bytes(record[0])),
+            source: PathBuf::from(OsStr::from_bytes(record[1])),
+            inode,
+        });
+    }
+    return Ok(records);
+}
+
+/// Replaces the records of the destination, written to a temporary file first,
+/// so that an interrupted run leaves the previous records.
+pub fn write_link_records(destination: &Path, records: &[LinkRecord]) -> Result<(), Failure> {
+    let mut content: Vec<u8> = Vec::new();
+    for record in records {
+        content.extend_from_slice(record.link.as_os_str().as_bytes());
+        content.push(0);
+        content.extend_from_slice(record.source.as_os_str().as_bytes());
+        content.push(0);
+        content.extend_from_slice(record.inode.to_string().as_bytes());
+        content.push(0);
+    }
+    let temporary: PathBuf = destination.join(format!("{}.tmp", MANIFEST_NAME));
+    return fs::write(&temporary, content)
+        .and_then(|_| fs::rename(&temporary, destination.join(MANIFEST_NAME)))
+        .map_err(|e| manifest_io(destination, e));
+}
+
+/// Records of the links that are in place after the operations were executed: the ones made
+/// by the operations and the intact ones of previous runs.
+pub fn record_links(destination: &Path,
+                    records: Vec<LinkRecord>,
+                    operations: &[RenameOperation],
+                    transfer: Transfer) -> Result<Vec<LinkRecord>, Failure> {
+    let mut made: Vec<LinkRecord> = Vec::with_capacity(operations.len());
+    for o in operations {
+        let source: PathBuf = fs::canonicalize(&o.from)
+            .map_err(|e| Failure::file_io(o.from.to_string(), "Failed to resolve linked file".to_string(), e))?;
+        let inode: u64 = fs::metadata(&source)
+            .map_err(|e| Failure::file_io(o.from.to_string(), "Failed to get file metadata".to_string(), e))?
+            .ino();
+        let link: PathBuf = match Path::new(&o.to).strip_prefix(destination) {
+            Ok(link) => link.to_path_buf(),
+            Err(_) => continue
+        };
+        made.push(LinkRecord { link, source, inode });
+    }
+    let kept = records.into_iter()
+        .filter(|r| !made.iter().any(|m| m.link == r.link) && r.is_intact(destination, transfer));
+    let mut records: Vec<LinkRecord> = kept.collect();
+    records.extend(made);
+    return Ok(records);
+}
+
+/// Intact recorded links of the given sources, by their paths. They are made again along with
+/// the new links of the sources, so that all of them are numbered in order.
+pub fn links_of_sources(destination: &Path,
+                        records: &[LinkRecord],
+                        sources: &HashSet<PathBuf>,
+                        transfer: Transfer) -> HashSet<PathBuf> {
+    return records.iter()
+        // hard links to replaced sources keep the previous content, only pruning removes them:
+        .filter(|r| sources.contains(&r.source) && r.is_intact(destination, transfer) && !r.source_gone())
+        .map(|r| destination.join(&r.link))
+        .collect();
+}
+
+/// Recorded links that are still intact but whose sources are gone.
+pub fn links_to_prune(destination: &Path, records: &[LinkRecord], transfer: Transfer) -> Vec<PathBuf> {
+    return records.iter()
+        .filter(|r| r.is_intact(destination, transfer) && r.source_gone())
+        .map(|r| destination.join(&r.link))
+        .collect();
+}
+
+pub fn remove_links(links: &[PathBuf]) -> Result<(), Failure> {
+    for link in links {
+        fs::remove_file(link)
+            .map_err(|e| Failure::file_io(
+                link.to_string_lossy().to_string(),
+                "Failed to remove link".to_string(),
+                e))?;
+    }
+    return Ok(());
+}
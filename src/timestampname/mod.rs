@@ -1,101 +1,670 @@
 pub mod failures;
+pub mod timezone;
+pub mod clock;
+pub mod filename;
+pub mod template;
+mod timestamp;
 mod renamer;
 mod verifier;
 mod executor;
+mod links;
 mod extractor;
+mod traversal;
 
-use std::fs::ReadDir;
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
 use std::io;
+use std::io::Write;
+use std::path::Path;
 use std::path::PathBuf;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::mpsc;
+use std::thread;
+
+use chrono::DateTime;
+use chrono::Utc;
 
 use super::timestampname::failures::Failure;
 
 pub struct CommandLineArguments {
     pub dry_run: bool,
-    pub no_prefix: bool,
+    pub name_template: template::NameTemplate,
     pub debug_output: bool,
     pub utc: bool,
+    pub mp4_clock_rules: Vec<(String, timezone::Mp4Clock)>,
+    pub date_priority: DatePriority,
+    pub jobs: usize,
+    pub keep_going: bool,
+    pub target_zone: Option<timezone::TargetZone>,
+    pub gps_time: bool,
+    pub clock_offsets: bool,
+    pub camera_offsets: Vec<clock::CameraOffset>,
+    // file names of images shot at the same moment, reference first:
+    pub offset_pairs: Vec<(String, String)>,
+    pub shift: Option<chrono::Duration>,
+    pub fallbacks: Vec<Fallback>,
+    pub name_patterns: Vec<filename::FileNamePattern>,
+    pub unsupported_policy: UnsupportedPolicy,
+    pub recursive: bool,
+    pub include_hidden: bool,
+    pub exclusions: Vec<String>,
+    pub numbering: Numbering,
+    // directories and files to rename, the working directory if empty:
+    pub paths: Vec<PathBuf>,
+    // file with a list of paths to add to the ones above, `-` for standard input:
+    pub files_from: Option<String>,
+    // root directory to move files into instead of renaming them in place:
+    pub destination: Option<PathBuf>,
+    pub directory_template: Option<template::DirectoryTemplate>,
+    pub transfer: Transfer,
+    // remove sources of verified copies:
+    pub delete_source: bool,
+    // remove links in the destination whose sources are gone:
+    pub prune: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExifDateTag {
+    /// DateTimeOriginal: when the shutter was released.
+    Original,
+    /// DateTimeDigitized: when the image was digitized, differs from original for scans.
+    Digitized,
+    /// DateTime: when the file was last changed, for example by an editor.
+    Modified,
+}
+
+pub enum DatePriority {
+    /// Lexically earliest of all date tags, regardless of the tag.
+    Earliest,
+    /// First of the listed tags present in the file, tags not listed are never used.
+    Ordered(Vec<ExifDateTag>),
+}
+
+impl Default for DatePriority {
+    fn default() -> Self {
+        return DatePriority::Ordered(vec![ExifDateTag::Original, ExifDateTag::Digitized, ExifDateTag::Modified]);
+    }
+}
+
+impl DatePriority {
+    /// Parses `earliest` or a comma separated list of `original`, `digitized` and `modified`.
+    pub fn parse(value: &str) -> Option<DatePriority> {
+        if value == "earliest" {
+            return Some(DatePriority::Earliest);
+        }
+        let mut tags: Vec<ExifDateTag> = Vec::new();
+        for name in value.split(',') {
+            let tag = match name.trim() {
+                "original" => ExifDateTag::Original,
+                "digitized" => ExifDateTag::Digitized,
+                "modified" => ExifDateTag::Modified,
+                _ => return None
+            };
+            if tags.contains(&tag) {
+                return None;
+            }
+            tags.push(tag);
+        }
+        return Some(DatePriority::Ordered(tags));
+    }
+}
+
+/// MP4 boxes holding creation time in the same layout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mp4Header {
+    /// mvhd, the whole movie.
+    Movie,
+    /// tkhd, a single track.
+    Track,
+    /// mdhd, media of a single track.
+    Media,
+}
+
+/// What to do with files of unsupported types.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnsupportedPolicy {
+    /// Leave them untouched, only counted in the summary.
+    Skip,
+    /// Leave them untouched and list every one of them.
+    Report,
+    /// Rename nothing if any are present.
+    Fail,
+    /// Rename them using the fallback timestamps.
+    Include,
+}
+
+impl UnsupportedPolicy {
+    pub fn parse(value: &str) -> Option<UnsupportedPolicy> {
+        return match value {
+            "skip" => Some(UnsupportedPolicy::Skip),
+            "report" => Some(UnsupportedPolicy::Report),
+            "fail" => Some(UnsupportedPolicy::Fail),
+            "include" => Some(UnsupportedPolicy::Include),
+            _ => None
+        };
+    }
+}
+
+/// How prefix counters are assigned to files of subdirectories.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Numbering {
+    /// Files are renamed in place, every directory has its own counter.
+    PerDirectory,
+    /// Files of all directories are numbered in one sequence and moved into the top directory,
+    /// the one given on the command line or the working directory, or into the destination.
+    Global,
+}
+
+impl Numbering {
+    pub fn parse(value: &str) -> Option<Numbering> {
+        return match value {
+            "directory" => Some(Numbering::PerDirectory),
+            "global" => Some(Numbering::Global),
+            _ => None
+        };
+    }
+}
+
+/// How files get their new names.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transfer {
+    /// Renamed or moved, copied and removed only across file systems.
+    Move,
+    /// Copied and verified by checksum, sources are left untouched.
+    Copy,
+    /// Hard links to the sources, which keep their names.
+    HardLink,
+    /// Symbolic links to absolute paths of the sources, which keep their names.
+    SymbolicLink,
+}
+
+/// Where to take timestamps from for supported files without one in their metadata.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fallback {
+    FileName,
+    ModificationTime,
+    /// Creation time of the file on the file system, not available everywhere.
+    BirthTime,
+}
+
+/// Parses a comma separated list of `filename`, `mtime` and `birthtime`, in the order to try them.
+pub fn parse_fallbacks(value: &str) -> Option<Vec<Fallback>> {
+    let mut fallbacks: Vec<Fallback> = Vec::new();
+    for name in value.split(',') {
+        let fallback = match name.trim() {
+            "filename" => Fallback::FileName,
+            "mtime" => Fallback::ModificationTime,
+            "birthtime" => Fallback::BirthTime,
+            _ => return None
+        };
+        if fallbacks.contains(&fallback) {
+            return None;
+        }
+        fallbacks.push(fallback);
+    }
+    return Some(fallbacks);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimestampSource {
+    Exif(ExifDateTag),
+    /// Creation time of a header box and how its writer stores it.
+    Mp4CreationTime(Mp4Header, timezone::Mp4Clock),
+    /// udta/©day, date text with optional time zone.
+    Mp4UserDataDate,
+    /// com.apple.quicktime.creationdate key of the meta box, date text with time zone.
+    Mp4AppleCreationDate,
+    /// GPSDateStamp and GPSTimeStamp, UTC time received from satellites.
+    GpsTimeStamp,
+    /// Date and time encoded in the file name, used only if the file has none in its metadata.
+    FileName,
+    /// File system modification time, used only if the file has no timestamp in its metadata.
+    ModificationTime,
+    /// File system creation time, used only if the file has no timestamp in its metadata.
+    BirthTime,
+}
+
+impl TimestampSource {
+    /// Whether the timestamp is a guess made for a file without one in its metadata.
+    pub fn is_fallback(&self) -> bool {
+        return matches!(self, TimestampSource::FileName | TimestampSource::ModificationTime | TimestampSource::BirthTime);
+    }
+}
+
+impl fmt::Display for TimestampSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match self {
+            TimestampSource::Exif(ExifDateTag::Original) => write!(f, "DateTimeOriginal"),
+            TimestampSource::Exif(ExifDateTag::Digitized) => write!(f, "DateTimeDigitized"),
+            TimestampSource::Exif(ExifDateTag::Modified) => write!(f, "DateTime"),
+            TimestampSource::Mp4CreationTime(header, clock) => write!(
+                f, "{} creation time, {}",
+                match header {
+                    Mp4Header::Movie => "mvhd",
+                    Mp4Header::Track => "tkhd",
+                    Mp4Header::Media => "mdhd",
+                },
+                match clock {
+                    timezone::Mp4Clock::Utc => "UTC",
+                    timezone::Mp4Clock::Local => "local",
+                }),
+            TimestampSource::Mp4UserDataDate => write!(f, "\u{a9}day"),
+            TimestampSource::Mp4AppleCreationDate => write!(f, "com.apple.quicktime.creationdate"),
+            TimestampSource::GpsTimeStamp => write!(f, "GPSTimeStamp"),
+            TimestampSource::FileName => write!(f, "file name, low confidence"),
+            TimestampSource::ModificationTime => write!(f, "file modification time, low confidence"),
+            TimestampSource::BirthTime => write!(f, "file creation time, low confidence"),
+        }
+    }
 }
 
 pub struct FileMetadata {
+    // path relative to the working directory:
     file_name: String,
-    creation_timestamp: String,
+    timestamp: timestamp::Timestamp,
+    // EXIF Make, Model and BodySerialNumber tags, identifying the camera:
+    camera_make: Option<String>,
+    camera_model: Option<String>,
+    camera_serial: Option<String>,
+    // EXIF LensModel tag:
+    lens_model: Option<String>,
+    // known for photos with EXIF GPS date and time tags:
+    gps_timestamp: Option<DateTime<Utc>>,
     extension: String
 }
 
 pub struct RenameOperation {
     from: String,
     to: String,
+    source: TimestampSource,
+}
+
+struct SkippedFile {
+    file_name: String,
+    // lowercase, empty for files without extension:
+    extension: String,
+    reason: &'static str,
 }
 
 struct CollectedMetadata {
     items: Vec<FileMetadata>,
+    failures: Vec<Failure>,
+    skipped: Vec<SkippedFile>,
     longest_source_name: usize,
 }
 
-pub fn execute(cwd: PathBuf, cmd_args: CommandLineArguments) -> Result<(), Failure> {
+pub enum Outcome {
+    /// Every supported file was processed.
+    Success,
+    /// Some files failed and were left untouched, the rest were processed.
+    PartialSuccess,
+    /// Supported files were found, but none of them could be processed.
+    TotalFailure,
+}
+
+pub fn execute(cwd: PathBuf, cmd_args: CommandLineArguments) -> Result<Outcome, Failure> {
     print!("Scanning for files...");
-    let files: Vec<PathBuf> = list_files(cwd)?;
+    let traversal_options = traversal::TraversalOptions {
+        recursive: cmd_args.recursive,
+        include_hidden: cmd_args.include_hidden,
+        exclusions: cmd_args.exclusions,
+    };
+    let top_directory: PathBuf = cmd_args.paths.first().cloned().unwrap_or_default();
+    let files: Vec<PathBuf> = match (cmd_args.paths.is_empty(), &cmd_args.files_from) {
+        // listed relative to the working directory, so that names are shown as they are:
+        (true, None) => traversal::list_files(&cwd, &traversal_options)?,
+        (_, files_from) => {
+            let mut paths: Vec<PathBuf> = cmd_args.paths;
+            if let Some(files_from) = files_from {
+                paths.extend(traversal::read_file_list(files_from)?);
+            }
+            traversal::collect_files(&paths, &traversal_options)?
+        }
+    };
     println!(" {} files found.", files.len());
 
-    let collected_metadata: CollectedMetadata = process_files(files, cmd_args.utc)?;
+    let extractor_options = extractor::ExtractorOptions {
+        mp4_default_clock: match cmd_args.utc {
+            true => timezone::Mp4Clock::Local,
+            false => timezone::Mp4Clock::Utc
+        },
+        mp4_clock_rules: cmd_args.mp4_clock_rules,
+        date_priority: cmd_args.date_priority,
+        fallbacks: cmd_args.fallbacks,
+        // user patterns are more specific, so they are tried first:
+        file_name_patterns: cmd_args.name_patterns.into_iter()
+            .chain(filename::built_in_file_name_patterns())
+            .collect(),
+        include_unsupported: cmd_args.unsupported_policy == UnsupportedPolicy::Include,
+    };
+    let mut collected_metadata: CollectedMetadata = process_files(
+        files, &extractor_options, cmd_args.jobs, cmd_args.keep_going, cmd_args.debug_output)?;
+
+    if !collected_metadata.skipped.is_empty() {
+        print_skipped_summary(&collected_metadata.skipped, cmd_args.unsupported_policy);
+        if cmd_args.unsupported_policy == UnsupportedPolicy::Fail {
+            println!("\nUnsupported files found, nothing was renamed.");
+            return Ok(Outcome::TotalFailure);
+        }
+    }
 
     if collected_metadata.items.is_empty() {
+        if !collected_metadata.failures.is_empty() {
+            print_failures_summary(&collected_metadata.failures);
+            return Ok(Outcome::TotalFailure);
+        }
         println!("No supported files found.");
-        return Ok(());
+        return Ok(Outcome::Success);
+    }
+
+    let guessed: usize = collected_metadata.items.iter()
+        .filter(|md| md.timestamp.source.is_fallback())
+        .count();
+    if guessed > 0 {
+        println!("Timestamps of {} files without metadata were guessed by fallbacks, check them in the listing.",
+                 guessed);
+    }
+
+    if cmd_args.clock_offsets {
+        clock::print_clock_offsets(&collected_metadata.items);
+        return Ok(Outcome::Success);
+    }
+
+    if !cmd_args.offset_pairs.is_empty() {
+        return match clock::print_derived_offsets(&collected_metadata.items, &cmd_args.offset_pairs) {
+            true => Ok(Outcome::Success),
+            false => Ok(Outcome::TotalFailure)
+        };
+    }
+
+    if !cmd_args.camera_offsets.is_empty() {
+        print!("Correcting camera clocks...");
+        let corrected = clock::apply_camera_offsets(&mut collected_metadata.items, &cmd_args.camera_offsets);
+        println!(" done, {} files corrected.", corrected);
+    }
+
+    if cmd_args.gps_time {
+        print!("Using GPS time...");
+        let replaced = clock::use_gps_timestamps(&mut collected_metadata.items);
+        println!(" done, {} of {} files have GPS time.", replaced, collected_metadata.items.len());
+    }
+
+    if let Some(shift) = cmd_args.shift {
+        print!("Shifting timestamps by {}...", clock::format_clock_offset(shift));
+        clock::shift_timestamps(&mut collected_metadata.items, shift);
+        println!(" done.");
+    }
+
+    if let Some(target_zone) = &cmd_args.target_zone {
+        print!("Normalizing time zones...");
+        let untouched = timezone::normalize_timezones(&mut collected_metadata.items, target_zone);
+        match untouched {
+            0 => println!(" done."),
+            u => println!(" done, {} files without time zone information left as is.", u)
+        }
     }
 
+    let destination = match (cmd_args.destination, cmd_args.directory_template) {
+        (Some(root), template) => renamer::Destination::Tree(root, template),
+        (None, _) if cmd_args.numbering == Numbering::Global => renamer::Destination::Directory(top_directory),
+        (None, _) => renamer::Destination::InPlace
+    };
+    let (relinked, pruned): (HashSet<PathBuf>, Vec<PathBuf>) = match (cmd_args.transfer, &destination) {
+        (Transfer::HardLink | Transfer::SymbolicLink, renamer::Destination::Tree(root, _)) => plan_links(
+            root, &collected_metadata.items, cmd_args.transfer, cmd_args.prune, cmd_args.dry_run)?,
+        _ => (HashSet::new(), Vec::new())
+    };
+    // names of these links are free for the new ones:
+    let mut renewed: HashSet<PathBuf> = relinked.clone();
+    renewed.extend(pruned.iter().cloned());
     print!("Preparing rename operations...");
     let operations: Vec<RenameOperation>
-        = renamer::prepare_rename_operations(collected_metadata.items, cmd_args.no_prefix)?;
+        = renamer::prepare_rename_operations(
+        collected_metadata.items, &cmd_args.name_template, cmd_args.numbering, &destination, cmd_args.transfer, &renewed)?;
     println!(" done.");
+    // links that keep their names stay in place:
+    let mut removed: Vec<PathBuf> = renewed.into_iter()
+        .filter(|link| pruned.contains(link) || !operations.iter().any(|o| Path::new(&o.to) == link))
+        .collect();
+    removed.sort();
 
     println!("Verifying:");
-    verifier::verify_operations(&operations, collected_metadata.longest_source_name)?;
+    verifier::verify_operations(&operations, collected_metadata.longest_source_name, cmd_args.transfer, &removed)?;
     println!("done.");
 
-    executor::execute_operations(&operations, cmd_args.dry_run)?;
+    if !removed.is_empty() {
+        print!("Removing {} links of previous runs...", removed.len());
+        if !cmd_args.dry_run {
+            links::remove_links(&removed)?;
+        }
+        println!(" done.");
+    }
+
+    executor::execute_operations(&operations, cmd_args.transfer, cmd_args.delete_source, cmd_args.dry_run)?;
+
+    if let (Transfer::HardLink | Transfer::SymbolicLink, renamer::Destination::Tree(root, _), false)
+        = (cmd_args.transfer, &destination, cmd_args.dry_run) {
+        let records = links::record_links(root, links::read_link_records(root)?, &operations, cmd_args.transfer)?;
+        links::write_link_records(root, &records)?;
+    }
+
+    if !collected_metadata.failures.is_empty() {
+        print_failures_summary(&collected_metadata.failures);
+        println!("\nFinished with failures.");
+        return Ok(Outcome::PartialSuccess);
+    }
 
     println!("\nFinished.");
-    return Ok(());
+    return Ok(Outcome::Success);
 }
 
-fn list_files(cwd: PathBuf) -> Result<Vec<PathBuf>, Failure> {
-    let read_dir_res: Result<ReadDir, io::Error> = cwd.read_dir();
-    let files: Result<Vec<PathBuf>, io::Error> = read_dir_res.and_then(|iterator| {
-        let mut vec: Vec<PathBuf> = Vec::new();
-        for entry in iterator {
-            let entry = entry?;
-            if entry.file_type()?.is_file() {
-                vec.push(entry.path());
-            }
+// links are recorded in the destination, so that pruning never touches files put there otherwise.
+// Recorded links of the files are made again, so that files added later are numbered in order
+// with them, links to prune are confirmed before and removed along with them:
+fn plan_links(root: &Path,
+              items: &[FileMetadata],
+              transfer: Transfer,
+              prune: bool,
+              dry_run: bool) -> Result<(HashSet<PathBuf>, Vec<PathBuf>), Failure> {
+    let records: Vec<links::LinkRecord> = links::read_link_records(root)?;
+    let sources: HashSet<PathBuf> = items.iter()
+        .filter_map(|md| fs::canonicalize(&md.file_name).ok())
+        .collect();
+    let relinked: HashSet<PathBuf> = links::links_of_sources(root, &records, &sources, transfer);
+    let mut pruned: Vec<PathBuf> = Vec::new();
+    if prune {
+        pruned = links::links_to_prune(root, &records, transfer);
+        pruned.sort();
+        println!("Links to prune ({}):", pruned.len());
+        for link in &pruned {
+            println!("    {}", link.display());
         }
-        return Ok(vec);
-    });
-    let res: Result<Vec<PathBuf>, Failure> = files
-        .map_err(|e| Failure::env_failure_caused("Failed to list directory contents".to_string(), e));
-    return res;
+        if !dry_run && !pruned.is_empty() && !confirm("Remove these links?") {
+            println!("Links were kept.");
+            pruned.clear();
+        }
+    }
+    return Ok((relinked, pruned));
 }
 
-fn process_files(files: Vec<PathBuf>, utc: bool) -> Result<CollectedMetadata, Failure> {
+// anything but yes is no, including the end of input:
+fn confirm(question: &str) -> bool {
+    print!("{} [y/N] ", question);
+    let _ = io::stdout().flush();
+    let mut answer: String = String::new();
+    if io::stdin().read_line(&mut answer).is_err() {
+        return false;
+    }
+    return matches!(answer.trim().to_lowercase().as_str(), "y" | "yes");
+}
+
+fn print_skipped_summary(skipped: &[SkippedFile], policy: UnsupportedPolicy) {
+    // extension and count, in the order of first appearance:
+    let mut counts: Vec<(&str, usize)> = Vec::new();
+    for file in skipped {
+        match counts.iter_mut().find(|(ext, _)| *ext == file.extension) {
+            Some(count) => count.1 += 1,
+            None => counts.push((&file.extension, 1))
+        }
+    }
+    counts.sort_by(|c1, c2| c2.1.cmp(&c1.1).then(c1.0.cmp(c2.0)));
+    let counts: Vec<String> = counts.iter()
+        .map(|(ext, count)| match ext.is_empty() {
+            true => format!("{} without extension", count),
+            false => format!("{} {}", count, ext)
+        })
+        .collect();
+    println!("Skipped files ({}): {}.", skipped.len(), counts.join(", "));
+    if policy == UnsupportedPolicy::Report || policy == UnsupportedPolicy::Fail {
+        let width: usize = skipped.iter().map(|f| f.file_name.len()).max().unwrap_or(0);
+        for file in skipped {
+            println!("    {:width$}    {}", file.file_name, file.reason, width = width);
+        }
+    }
+}
+
+fn print_failures_summary(failures: &[Failure]) {
+    let width: usize = failures.iter()
+        .map(|f| f.file_name().map_or(0, |n| n.len()))
+        .max()
+        .unwrap_or(0);
+    println!("\nFailed files ({}), left untouched:", failures.len());
+    for failure in failures {
+        println!("    {:width$}    {}",
+                 failure.file_name().unwrap_or(""),
+                 failure.reason(),
+                 width = width);
+    }
+}
+
+fn process_files(files: Vec<PathBuf>,
+                 options: &extractor::ExtractorOptions,
+                 jobs: usize,
+                 keep_going: bool,
+                 debug_output: bool) -> Result<CollectedMetadata, Failure> {
+    let jobs: usize = match jobs {
+        0 => thread::available_parallelism().map_or(1, |n| n.get()),
+        j => j
+    };
+    if debug_output {
+        println!("Extracting metadata with {} worker(s).", jobs.min(files.len()).max(1));
+    }
+    let extracted: Vec<Result<Option<FileMetadata>, Failure>> = match jobs {
+        1 => extract_sequentially(&files, options, keep_going),
+        _ => extract_concurrently(&files, options, jobs)
+    };
     let mut res: Vec<FileMetadata> = Vec::new();
+    let mut failures: Vec<Failure> = Vec::new();
+    let mut skipped: Vec<SkippedFile> = Vec::new();
     let mut longest_source_name: usize = 0;
-    for (index, element) in files.iter().enumerate() {
-        print!("\rProcessing files: {}/{}...", index + 1, files.len());
-        let md: Option<FileMetadata> = extractor::extract_metadata_creation_timestamp(element, utc)?;
+    // failure of the first file in listing order wins, regardless of the number of workers:
+    for (file, md) in files.iter().zip(extracted) {
         match md {
-            Some(x) => {
+            Ok(Some(x)) => {
                 if x.file_name.len() > longest_source_name {
                     longest_source_name = x.file_name.len();
                 }
                 res.push(x);
             }
-            _ => {}
+            Ok(None) => skipped.push(SkippedFile {
+                file_name: file.to_string_lossy().to_string(),
+                extension: file.extension().map_or(String::new(), |x| x.to_string_lossy().to_lowercase()),
+                reason: match options.include_unsupported {
+                    true => "unsupported file type, no fallback timestamp found",
+                    false => "unsupported file type"
+                },
+            }),
+            Err(e) => {
+                if !keep_going {
+                    return Err(e);
+                }
+                failures.push(e);
+            }
         }
     }
-    println!(" {} supported files found.", res.len());
+    match failures.len() {
+        0 => println!(" {} supported files found.", res.len()),
+        f => println!(" {} supported files found, {} failed.", res.len() + f, f)
+    }
+    if debug_output {
+        print_box_trees(&files);
+    }
     return Ok(CollectedMetadata {
         items: res,
+        failures,
+        skipped,
         longest_source_name,
     });
 }
+
+fn print_box_trees(files: &[PathBuf]) {
+    for file in files {
+        match extractor::quicktime_box_tree(file) {
+            Ok(Some(boxes)) => {
+                println!("Boxes of {}:", file.display());
+                for b in boxes {
+                    println!("    {:indent$}{} @ {}, {} bytes", "", b.box_type, b.offset, b.size, indent = b.depth * 2);
+                }
+            }
+            Ok(None) => {}
+            Err(e) => println!("Boxes of {}: {}", file.display(), e.reason())
+        }
+    }
+}
+
+fn extract_sequentially(files: &[PathBuf],
+                        options: &extractor::ExtractorOptions,
+                        keep_going: bool) -> Vec<Result<Option<FileMetadata>, Failure>> {
+    let mut res: Vec<Result<Option<FileMetadata>, Failure>> = Vec::with_capacity(files.len());
+    for (index, element) in files.iter().enumerate() {
+        print!("\rProcessing files: {}/{}...", index + 1, files.len());
+        let md = extractor::extract_metadata_creation_timestamp(element, options);
+        let failed = md.is_err();
+        res.push(md);
+        if failed && !keep_going {
+            break;
+        }
+    }
+    return res;
+}
+
+fn extract_concurrently(files: &[PathBuf],
+                        options: &extractor::ExtractorOptions,
+                        jobs: usize) -> Vec<Result<Option<FileMetadata>, Failure>> {
+    // workers pull the next file index from the shared counter and send results back
+    // tagged with that index, so the output keeps the listing order regardless of
+    // which worker finished first:
+    let next_index = AtomicUsize::new(0);
+    let (sender, receiver) = mpsc::channel::<(usize, Result<Option<FileMetadata>, Failure>)>();
+    let mut slots: Vec<Option<Result<Option<FileMetadata>, Failure>>> = Vec::with_capacity(files.len());
+    slots.resize_with(files.len(), || None);
+
+    thread::scope(|scope| {
+        for _ in 0..jobs.min(files.len()) {
+            let sender = sender.clone();
+            let next_index = &next_index;
+            scope.spawn(move || loop {
+                let index = next_index.fetch_add(1, Ordering::SeqCst);
+                if index >= files.len() {
+                    break;
+                }
+                let md = extractor::extract_metadata_creation_timestamp(&files[index], options);
+                if sender.send((index, md)).is_err() {
+                    break;
+                }
+            });
+        }
+        // only worker clones should keep the channel open:
+        drop(sender);
+        for (processed, (index, md)) in receiver.iter().enumerate() {
+            print!("\rProcessing files: {}/{}...", processed + 1, files.len());
+            slots[index] = Some(md);
+        }
+    });
+
+    return slots.into_iter()
+        .map(|slot| slot.expect("file was not processed by any worker"))
+        .collect();
+}
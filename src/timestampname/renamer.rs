@@ -1,15 +1,45 @@
 use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+use std::path::PathBuf;
+
+use regex::Regex;
 
 use super::FileMetadata;
+use super::Numbering;
+use super::template::DirectoryTemplate;
+use super::template::check_file_name;
+use super::template::NameTemplate;
 use super::RenameOperation;
+use super::Transfer;
 use super::failures::Failure;
 
+/// Where renamed files are put.
+pub enum Destination {
+    /// Directory of every file, renamed in place.
+    InPlace,
+    /// One directory for all files.
+    Directory(PathBuf),
+    /// Root directory and the template of its subdirectories, files are moved into the root
+    /// itself without template.
+    Tree(PathBuf, Option<DirectoryTemplate>),
+}
+
+fn base_name(md: &FileMetadata) -> &str {
+    return Path::new(&md.file_name).file_name().and_then(|f| f.to_str()).unwrap_or(&md.file_name);
+}
+
 fn compare_metadatas(md1: &FileMetadata, md2: &FileMetadata) -> Ordering {
     if md1.file_name == md2.file_name {
         panic!("file encountered twice: {}", md1.file_name);
     }
-    let ct1 = &md1.creation_timestamp;
-    let ct2 = &md2.creation_timestamp;
+    // fraction of a second is part of the timestamp, burst shots within the same second
+    // are ordered by subseconds if known:
+    let ct1 = &md1.timestamp.local;
+    let ct2 = &md2.timestamp.local;
     match ct1 {
         ct if ct < ct2 => Ordering::Less,
         ct if ct > ct2 => Ordering::Greater,
@@ -17,8 +47,8 @@ fn compare_metadatas(md1: &FileMetadata, md2: &FileMetadata) -> Ordering {
             // workaround for Android way of dealing with same-second shots:
             // 20180430_184327.jpg
             // 20180430_184327(0).jpg
-            let l1: usize = md1.file_name.len();
-            let l2: usize = md2.file_name.len();
+            let l1: usize = base_name(md1).len();
+            let l2: usize = base_name(md2).len();
             match l1 {
                 l if l < l2 => Ordering::Less,
                 l if l > l2 => Ordering::Greater,
@@ -37,39 +67,206 @@ fn compare_metadatas(md1: &FileMetadata, md2: &FileMetadata) -> Ordering {
     }
 }
 
+// counters of folders that later runs add to are padded ahead, so that their names keep sorting in order:
+const DESTINATION_COUNTER_WIDTH: usize = 4;
+
 fn prefix_width(count: usize) -> usize {
-    match count {
-        c if c < 10 => 1,
-        c if c < 100 => 2,
-        c if c < 1000 => 3,
-        c if c < 10_000 => 4,
-        c if c < 100_000 => 5,
-        _ => panic!("too many files!")
-    }
+    return count.to_string().len();
 }
 
-pub fn prepare_rename_operations(mut items: Vec<FileMetadata>, no_prefix: bool) -> Result<Vec<RenameOperation>, Failure> {
-    let prefix_width: usize = prefix_width(items.len());
-    items.sort_unstable_by(compare_metadatas);
-    let sorted: Vec<FileMetadata> = items;
+fn directory(md: &FileMetadata) -> &Path {
+    return Path::new(&md.file_name).parent().unwrap_or(Path::new(""));
+}
 
-    let mut operations: Vec<RenameOperation> = Vec::new();
-    for (i, f) in sorted.iter().enumerate() {
-        let to: String = match no_prefix {
-            true => format!("{}{}",
-                            f.creation_timestamp,
-                            f.extension),
-            false => format!("{:0>width$}-{}{}",
-                             i + 1,
-                             f.creation_timestamp,
-                             f.extension,
-                             width = prefix_width)
+fn target_directory(md: &FileMetadata, destination: &Destination) -> PathBuf {
+    return match destination {
+        Destination::InPlace => directory(md).to_path_buf(),
+        Destination::Directory(target) => target.to_path_buf(),
+        Destination::Tree(root, None) => root.to_path_buf(),
+        Destination::Tree(root, Some(template)) => root.join(template.render(md)),
+    };
+}
+
+// device and inode, the same file regardless of the path it is reached by:
+fn file_identity(path: &Path) -> Result<(u64, u64), Failure> {
+    let metadata = fs::metadata(path)
+        .map_err(|e| Failure::env_io(format!("Failed to access {}", path.display()), e))?;
+    return Ok((metadata.dev(), metadata.ino()));
+}
+
+/// Highest counter of the names in the folder that match the template and the widest one in digits,
+/// zeros if there are none. Files being renamed and renewed links, made again or pruned, do not count.
+fn last_counter(folder: &Path,
+                pattern: &Regex,
+                sources: &HashSet<(u64, u64)>,
+                renewed: &HashSet<PathBuf>) -> Result<(usize, usize), Failure> {
+    let entries = match fs::read_dir(folder) {
+        Ok(entries) => entries,
+        // created by the executor:
+        Err(_) if !folder.exists() => return Ok((0, 0)),
+        Err(e) => return Err(Failure::env_io(format!("Failed to list contents of {}", folder.display()), e))
+    };
+    let mut last: usize = 0;
+    let mut width: usize = 0;
+    for entry in entries {
+        let entry = entry
+            .map_err(|e| Failure::env_io(format!("Failed to list contents of {}", folder.display()), e))?;
+        let (counter, digits): (usize, usize) = match entry.file_name().to_str()
+            .and_then(|name| pattern.captures(name))
+            .and_then(|c| c[1].parse::<usize>().ok().map(|counter| (counter, c[1].len()))) {
+            Some(counter) => counter,
+            None => continue
         };
-        let operation = RenameOperation {
-            from: f.file_name.to_string(),
-            to,
+        if renewed.contains(&entry.path()) {
+            continue;
+        }
+        // links are not followed, links left dangling by removed sources still count:
+        let metadata = entry.metadata()
+            .map_err(|e| Failure::env_io(format!("Failed to access {}", entry.path().display()), e))?;
+        if !sources.contains(&(metadata.dev(), metadata.ino())) {
+            last = last.max(counter);
+            width = width.max(digits);
+        }
+    }
+    return Ok((last, width));
+}
+
+/// Links in the folder made by previous runs, by the identity of the file they link to.
+/// Only names that match the template are taken, symbolic links to the absolute path of the file
+/// as the executor makes them. Renewed links are left out.
+fn existing_links(folder: &Path,
+                  pattern: &Regex,
+                  transfer: Transfer,
+                  renewed: &HashSet<PathBuf>) -> Result<HashMap<(u64, u64), PathBuf>, Failure> {
+    let mut links: HashMap<(u64, u64), PathBuf> = HashMap::new();
+    let entries = match fs::read_dir(folder) {
+        Ok(entries) => entries,
+        Err(_) if !folder.exists() => return Ok(links),
+        Err(e) => return Err(Failure::env_io(format!("Failed to list contents of {}", folder.display()), e))
+    };
+    let mut paths: Vec<PathBuf> = Vec::new();
+    for entry in entries {
+        let entry = entry
+            .map_err(|e| Failure::env_io(format!("Failed to list contents of {}", folder.display()), e))?;
+        if entry.file_name().to_str().is_some_and(|name| pattern.is_match(name)) && !renewed.contains(&entry.path()) {
+            paths.push(entry.path());
+        }
+    }
+    // the lowest name is kept if there are several links to the same file:
+    paths.sort();
+    for path in paths {
+        let metadata = match transfer {
+            Transfer::HardLink => fs::symlink_metadata(&path).ok().filter(|m| m.file_type().is_file()),
+            Transfer::SymbolicLink => match (fs::read_link(&path), fs::canonicalize(&path)) {
+                (Ok(target), Ok(canonical)) if target == canonical => fs::metadata(&path).ok(),
+                _ => None
+            },
+            Transfer::Move | Transfer::Copy => None,
         };
-        operations.push(operation);
+        if let Some(metadata) = metadata {
+            links.entry((metadata.dev(), metadata.ino())).or_insert(path);
+        }
+    }
+    return Ok(links);
+}
+
+/// Recorded links of previous runs in `renewed` are removed or made again, their names and
+/// counters are free for the operations.
+pub fn prepare_rename_operations(items: Vec<FileMetadata>,
+                                 template: &NameTemplate,
+                                 numbering: Numbering,
+                                 destination: &Destination,
+                                 transfer: Transfer,
+                                 renewed: &HashSet<PathBuf>) -> Result<Vec<RenameOperation>, Failure> {
+    // every group is numbered separately, per directory the files end up in:
+    let groups: Vec<(PathBuf, Vec<FileMetadata>)> = match numbering {
+        Numbering::Global => vec![(PathBuf::new(), items)],
+        Numbering::PerDirectory => {
+            let mut groups: Vec<(PathBuf, Vec<FileMetadata>)> = Vec::new();
+            for item in items {
+                let target: PathBuf = target_directory(&item, destination);
+                match groups.iter_mut().find(|g| g.0 == target) {
+                    Some(group) => group.1.push(item),
+                    None => groups.push((target, vec![item]))
+                }
+            }
+            groups.sort_by(|g1, g2| g1.0.cmp(&g2.0));
+            groups
+        }
+    };
+
+    // numbering continues after the files of previous runs in destination folders:
+    let counter_pattern: Option<Regex> = match destination {
+        Destination::Tree(_, _) => template.counter_pattern(),
+        _ => None
+    };
+    // moved files are numbered again, copies and links of previous runs keep their numbers:
+    let mut sources: HashSet<(u64, u64)> = HashSet::new();
+    if counter_pattern.is_some() && transfer == Transfer::Move {
+        for group in &groups {
+            for item in &group.1 {
+                sources.insert(file_identity(Path::new(&item.file_name))?);
+            }
+        }
+    }
+    let linking: bool = matches!(transfer, Transfer::HardLink | Transfer::SymbolicLink);
+
+    let mut operations: Vec<RenameOperation> = Vec::new();
+    for (_, mut items) in groups {
+        let mut start: usize = 0;
+        let mut width: usize = 0;
+        // files linked by a previous run without a record keep their links, so running again
+        // does not duplicate them, recorded links are made again instead:
+        let mut links: HashMap<(u64, u64), PathBuf> = HashMap::new();
+        if let Some(pattern) = &counter_pattern {
+            let folders: HashSet<PathBuf> = items.iter().map(|f| target_directory(f, destination)).collect();
+            for folder in folders {
+                let (last, digits) = last_counter(&folder, pattern, &sources, renewed)?;
+                start = start.max(last);
+                width = width.max(digits).max(DESTINATION_COUNTER_WIDTH);
+                if linking {
+                    links.extend(existing_links(&folder, pattern, transfer, renewed)?);
+                }
+            }
+        }
+        let mut linked: Vec<(FileMetadata, PathBuf)> = Vec::new();
+        if !links.is_empty() {
+            let mut unlinked: Vec<FileMetadata> = Vec::with_capacity(items.len());
+            for item in items {
+                let link: Option<&PathBuf> = links.get(&file_identity(Path::new(&item.file_name))?)
+                    .filter(|l| l.parent() == Some(target_directory(&item, destination).as_path()));
+                match link {
+                    Some(link) => linked.push((item, link.to_path_buf())),
+                    None => unlinked.push(item)
+                }
+            }
+            items = unlinked;
+        }
+        for (f, link) in linked {
+            operations.push(RenameOperation {
+                from: f.file_name.to_string(),
+                to: link.to_string_lossy().to_string(),
+                source: f.timestamp.source,
+            });
+        }
+        let prefix_width: usize = prefix_width(start + items.len()).max(width);
+        items.sort_unstable_by(compare_metadatas);
+        let sorted: Vec<FileMetadata> = items;
+
+        for (i, f) in sorted.iter().enumerate() {
+            let name: String = template.render(f, start + i + 1, prefix_width);
+            // a stem of the original name may still make it invalid:
+            check_file_name(&name)
+                .map_err(|problem| Failure::conflict(
+                    f.file_name.to_string(),
+                    format!("template produces {}: {:?}", problem, name)))?;
+            let operation = RenameOperation {
+                from: f.file_name.to_string(),
+                to: target_directory(f, destination).join(name).to_string_lossy().to_string(),
+                source: f.timestamp.source,
+            };
+            operations.push(operation);
+        }
     }
 
     return Ok(operations);
@@ -0,0 +1,305 @@
+use std::fmt::Write;
+use std::path::Path;
+use std::path::PathBuf;
+
+use chrono::format::Item;
+use chrono::format::StrftimeItems;
+use chrono::NaiveDate;
+use chrono::Timelike;
+use regex::Regex;
+
+use super::FileMetadata;
+use super::clock::camera_name;
+
+const DEFAULT_DATE_FORMAT: &str = "%Y%m%d-%H%M%S";
+const PLACEHOLDERS: [&str; 10] = ["counter", "date", "subsec", "offset", "stem", "make", "model", "camera", "lens", "ext"];
+
+enum ExtensionCase {
+    Lower,
+    Upper,
+    /// As in the original file name.
+    Keep,
+}
+
+enum Part {
+    Literal(String),
+    /// Position of the file in its sequence, padded to the given width,
+    /// or to the width of the largest counter of the sequence.
+    Counter(Option<usize>),
+    /// strftime format of the wall clock time.
+    Date(String),
+    /// Number of digits of the fraction of a second.
+    Subseconds(usize),
+    /// UTC offset like +0200, empty if unknown.
+    Offset,
+    /// Original file name without extension.
+    Stem,
+    Make,
+    Model,
+    /// Make and model as shown by clock offsets, empty if unknown.
+    Camera,
+    Lens,
+    /// Extension with the leading dot, empty for files without one.
+    Extension(ExtensionCase),
+}
+
+/// Pattern of the new file names, validated so that it can only produce non-empty names
+/// without path separators.
+pub struct NameTemplate {
+    parts: Vec<Part>,
+}
+
+/// Pattern of the directories files are moved into, relative to the destination,
+/// one name template per directory level.
+pub struct DirectoryTemplate {
+    levels: Vec<NameTemplate>,
+}
+
+/// Template of the names without options: `{counter}-{date}{ext}`, without counter if
+/// `no_prefix` is set, with milliseconds appended to the date if `millis` is set.
+pub fn default_name_template(no_prefix: bool, millis: bool) -> NameTemplate {
+    let template = format!("{}{{date}}{}{{ext}}",
+                           if no_prefix { "" } else { "{counter}-" },
+                           if millis { "-{subsec:3}" } else { "" });
+    return parse_name_template(&template).expect("default name template is invalid");
+}
+
+/// Parses text with `{placeholder}` or `{placeholder:argument}` fields, `{{` and `}}` are literal braces.
+/// Returns a description of the problem for invalid templates.
+pub fn parse_name_template(value: &str) -> Result<NameTemplate, String> {
+    let parts: Vec<Part> = parse_parts(value)?;
+    // the only placeholders that are never empty:
+    if !parts.iter().any(|p| matches!(p, Part::Counter(_) | Part::Date(_) | Part::Stem)) {
+        return Err("{counter}, {date} or {stem} is required, other placeholders may be empty".to_string());
+    }
+    return Ok(NameTemplate { parts });
+}
+
+/// Parses directory levels separated by slashes, like `{date:%Y}/{date:%Y-%m}`,
+/// each level has the syntax of name templates. Counters are not known before files are
+/// assigned to directories, so they are not allowed.
+pub fn parse_directory_template(value: &str) -> Result<DirectoryTemplate, String> {
+    if value.starts_with('/') {
+        return Err("directories must be relative to the destination".to_string());
+    }
+    let mut levels: Vec<NameTemplate> = Vec::new();
+    for level in value.split('/') {
+        if level.is_empty() || level == "." || level == ".." {
+            return Err(format!("invalid directory name '{}'", level));
+        }
+        let parts: Vec<Part> = parse_parts(level)?;
+        if parts.iter().any(|p| matches!(p, Part::Counter(_))) {
+            return Err("{counter} is not allowed in directories".to_string());
+        }
+        levels.push(NameTemplate { parts });
+    }
+    return Ok(DirectoryTemplate { levels });
+}
+
+fn parse_parts(value: &str) -> Result<Vec<Part>, String> {
+    let mut parts: Vec<Part> = Vec::new();
+    let mut literal: String = String::new();
+    let mut chars = value.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                literal.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                literal.push('}');
+            }
+            '}' => return Err("unmatched '}', use '}}' for a literal brace".to_string()),
+            '{' => {
+                let mut field: String = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => field.push(c),
+                        None => return Err(format!("unclosed '{{{}'", field))
+                    }
+                }
+                if !literal.is_empty() {
+                    parts.push(Part::Literal(literal));
+                    literal = String::new();
+                }
+                parts.push(parse_placeholder(&field)?);
+            }
+            '/' | '\\' | '\0' => return Err("path separators are not allowed in file names".to_string()),
+            c if c.is_control() => return Err("control characters are not allowed in file names".to_string()),
+            c => literal.push(c)
+        }
+    }
+    if !literal.is_empty() {
+        parts.push(Part::Literal(literal));
+    }
+    return Ok(parts);
+}
+
+fn parse_placeholder(field: &str) -> Result<Part, String> {
+    let (name, argument) = match field.split_once(':') {
+        Some((name, argument)) => (name, Some(argument)),
+        None => (field, None)
+    };
+    let part = match (name, argument) {
+        ("counter", None) => Part::Counter(None),
+        ("counter", Some(width)) => Part::Counter(Some(parse_digits(width, "counter")?)),
+        ("date", None) => Part::Date(DEFAULT_DATE_FORMAT.to_string()),
+        ("date", Some(format)) => {
+            check_date_format(format)?;
+            Part::Date(format.to_string())
+        }
+        ("subsec", None) => Part::Subseconds(3),
+        ("subsec", Some(digits)) => Part::Subseconds(parse_digits(digits, "subsec")?),
+        ("ext", None) | ("ext", Some("lower")) => Part::Extension(ExtensionCase::Lower),
+        ("ext", Some("upper")) => Part::Extension(ExtensionCase::Upper),
+        ("ext", Some("keep")) => Part::Extension(ExtensionCase::Keep),
+        ("ext", Some(_)) => return Err("{ext} takes lower, upper or keep".to_string()),
+        ("offset", None) => Part::Offset,
+        ("stem", None) => Part::Stem,
+        ("make", None) => Part::Make,
+        ("model", None) => Part::Model,
+        ("camera", None) => Part::Camera,
+        ("lens", None) => Part::Lens,
+        (name, Some(_)) if PLACEHOLDERS.contains(&name) => return Err(format!("{{{}}} takes no argument", name)),
+        (name, _) => return Err(format!("unknown placeholder {{{}}}, expected one of: {}", name, PLACEHOLDERS.join(", ")))
+    };
+    return Ok(part);
+}
+
+fn parse_digits(value: &str, name: &str) -> Result<usize, String> {
+    return match value.parse::<usize>() {
+        Ok(n) if (1..=9).contains(&n) => Ok(n),
+        _ => Err(format!("{{{}}} takes a number of digits from 1 to 9", name))
+    };
+}
+
+fn check_date_format(format: &str) -> Result<(), String> {
+    if format.is_empty() {
+        return Err("{date:} requires a format, use {date} for the default one".to_string());
+    }
+    if StrftimeItems::new(format).any(|item| matches!(item, Item::Error)) {
+        return Err(format!("invalid date format: {}", format));
+    }
+    // formatting fails for fields the wall clock time does not have:
+    let sample = NaiveDate::from_ymd_opt(2001, 12, 31).unwrap().and_hms_opt(23, 59, 59).unwrap();
+    let mut formatted: String = String::new();
+    if write!(formatted, "{}", sample.format(format)).is_err() {
+        return Err(format!("date format {} has time zone fields, use {{offset}} instead", format));
+    }
+    // like %D, %x or %n:
+    return check_file_name(&formatted)
+        .map_err(|problem| format!("date format {} produces {}", format, problem));
+}
+
+/// Checks a rendered name or a part of it, returns the problem for names that cannot be
+/// used as file names or would name another directory.
+pub fn check_file_name(name: &str) -> Result<(), String> {
+    return match name {
+        "" => Err("an empty name".to_string()),
+        "." | ".." => Err(format!("directory name '{}'", name)),
+        _ if name.contains(['/', '\\']) => Err("path separators".to_string()),
+        _ if name.chars().any(|c| c.is_control()) => Err("control characters".to_string()),
+        _ => Ok(())
+    };
+}
+
+/// Free text of metadata, path separators in lens names like `f/2.8` and control characters are replaced.
+fn sanitize(value: &Option<String>) -> String {
+    return value.as_deref().unwrap_or("").replace(|c: char| c == '/' || c == '\\' || c.is_control(), "_");
+}
+
+impl NameTemplate {
+    /// Name of the file at the 1-based position of its sequence.
+    pub fn render(&self, md: &FileMetadata, counter: usize, counter_width: usize) -> String {
+        let path = Path::new(&md.file_name);
+        let mut name: String = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Literal(text) => name.push_str(text),
+                Part::Counter(width) => {
+                    name.push_str(&format!("{:0>width$}", counter, width = width.unwrap_or(counter_width)));
+                }
+                Part::Date(format) => name.push_str(&md.timestamp.local.format(format).to_string()),
+                Part::Subseconds(digits) => {
+                    // leap seconds are represented by nanoseconds over a second:
+                    let nanos = md.timestamp.local.nanosecond() % 1_000_000_000;
+                    name.push_str(&format!("{:09}", nanos)[..*digits]);
+                }
+                Part::Offset => {
+                    if let Some(offset) = md.timestamp.offset {
+                        let seconds = offset.local_minus_utc();
+                        let sign = if seconds < 0 { '-' } else { '+' };
+                        name.push_str(&format!("{}{:02}{:02}", sign, seconds.abs() / 3600, seconds.abs() / 60 % 60));
+                    }
+                }
+                Part::Stem => name.push_str(&path.file_stem().map_or(String::new(), |s| s.to_string_lossy().to_string())),
+                Part::Make => name.push_str(&sanitize(&md.camera_make)),
+                Part::Model => name.push_str(&sanitize(&md.camera_model)),
+                Part::Camera => {
+                    if md.camera_make.is_some() || md.camera_model.is_some() {
+                        name.push_str(&sanitize(&Some(camera_name(md))));
+                    }
+                }
+                Part::Lens => name.push_str(&sanitize(&md.lens_model)),
+                Part::Extension(case) => {
+                    let extension: String = match case {
+                        ExtensionCase::Lower => md.extension.to_lowercase(),
+                        ExtensionCase::Upper => md.extension.to_uppercase(),
+                        ExtensionCase::Keep => path.extension()
+                            .map_or(String::new(), |x| format!(".{}", x.to_string_lossy()))
+                    };
+                    // files without extension have just the dot:
+                    if extension != "." {
+                        name.push_str(&extension);
+                    }
+                }
+            }
+        }
+        return name;
+    }
+
+    /// Pattern of the names this template renders, capturing the first counter,
+    /// None if the template has no counter.
+    pub fn counter_pattern(&self) -> Option<Regex> {
+        if !self.parts.iter().any(|p| matches!(p, Part::Counter(_))) {
+            return None;
+        }
+        let mut pattern: String = String::from("^");
+        let mut captured: bool = false;
+        for part in &self.parts {
+            match part {
+                Part::Literal(text) => pattern.push_str(&regex::escape(text)),
+                Part::Counter(_) if !captured => {
+                    pattern.push_str(r"(\d+)");
+                    captured = true;
+                }
+                Part::Counter(_) => pattern.push_str(r"\d+"),
+                Part::Subseconds(digits) => pattern.push_str(&format!(r"\d{{{}}}", digits)),
+                // other fields depend on the file:
+                _ => pattern.push_str(".*?"),
+            }
+        }
+        pattern.push('$');
+        return Some(Regex::new(&pattern).expect("counter pattern of name template is invalid"));
+    }
+}
+
+impl DirectoryTemplate {
+    /// Directory of the file relative to the destination. Levels that come out empty,
+    /// like `{lens}` of files without one, are left out.
+    pub fn render(&self, md: &FileMetadata) -> PathBuf {
+        let mut directory: PathBuf = PathBuf::new();
+        for level in &self.levels {
+            let name: String = level.render(md, 0, 1);
+            match name.as_str() {
+                "" => {}
+                // camera make of just dots would escape the destination:
+                "." | ".." => directory.push("_"),
+                _ => directory.push(name)
+            }
+        }
+        return directory;
+    }
+}
@@ -0,0 +1,36 @@
+use chrono::DateTime;
+use chrono::FixedOffset;
+use chrono::NaiveDateTime;
+use chrono::TimeZone;
+
+use super::TimestampSource;
+
+/// Creation time of a file as recorded by its source, formatted only when names are generated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timestamp {
+    /// Wall clock time, including fraction of a second if it was recorded.
+    pub local: NaiveDateTime,
+    /// Known for timestamps with time zone information, like EXIF OffsetTime tags.
+    pub offset: Option<FixedOffset>,
+    pub source: TimestampSource,
+}
+
+impl Timestamp {
+    pub fn create(local: NaiveDateTime, offset: Option<FixedOffset>, source: TimestampSource) -> Timestamp {
+        return Timestamp {
+            local,
+            offset,
+            source,
+        };
+    }
+
+    pub fn zoned(instant: DateTime<FixedOffset>, source: TimestampSource) -> Timestamp {
+        return Timestamp::create(instant.naive_local(), Some(*instant.offset()), source);
+    }
+
+    /// Point in time, known only if the time zone is.
+    pub fn instant(&self) -> Option<DateTime<FixedOffset>> {
+        // fixed offsets have no gaps or folds, conversion is always unambiguous:
+        return self.offset.map(|offset| offset.from_local_datetime(&self.local).unwrap());
+    }
+}
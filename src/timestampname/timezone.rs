@@ -0,0 +1,94 @@
+use chrono::DateTime;
+use chrono::FixedOffset;
+use chrono::Local;
+use chrono::Offset;
+
+use super::FileMetadata;
+use super::timestamp::Timestamp;
+
+pub enum TargetZone {
+    /// Time zone of this machine, DST rules included.
+    Local,
+    Fixed(FixedOffset),
+}
+
+/// How a writer stores MP4 creation times.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mp4Clock {
+    /// UTC, as the specification suggests.
+    Utc,
+    /// Wall clock time of the zone the device was set to.
+    Local,
+}
+
+/// Parses `VENDOR=utc` or `VENDOR=local`, vendor names are case insensitive.
+pub fn parse_mp4_clock_rule(value: &str) -> Option<(String, Mp4Clock)> {
+    let (vendor, clock) = value.split_once('=')?;
+    let clock = match clock.to_lowercase().as_str() {
+        "utc" => Mp4Clock::Utc,
+        "local" => Mp4Clock::Local,
+        _ => return None
+    };
+    if vendor.trim().is_empty() {
+        return None;
+    }
+    return Some((vendor.trim().to_lowercase(), clock));
+}
+
+/// Parses offsets in EXIF format: `+HH:MM` or `-HH:MM`.
+pub fn parse_utc_offset(value: &str) -> Option<FixedOffset> {
+    let bytes = value.as_bytes();
+    if bytes.len() != 6
+        || bytes[3] != b':'
+        || !bytes[1..3].iter().all(|b| b.is_ascii_digit())
+        || !bytes[4..6].iter().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let sign: i32 = match bytes[0] {
+        b'+' => 1,
+        b'-' => -1,
+        _ => return None
+    };
+    let hours: i32 = value[1..3].parse().ok()?;
+    let minutes: i32 = value[4..6].parse().ok()?;
+    if hours > 23 || minutes > 59 {
+        return None;
+    }
+    return FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60));
+}
+
+/// Parses `local`, `utc` or an offset in `+HH:MM` format.
+pub fn parse_target_zone(value: &str) -> Option<TargetZone> {
+    return match value.to_lowercase().as_str() {
+        "local" => Some(TargetZone::Local),
+        "utc" => FixedOffset::east_opt(0).map(TargetZone::Fixed),
+        _ => parse_utc_offset(value).map(TargetZone::Fixed)
+    };
+}
+
+fn convert(timestamp: &DateTime<FixedOffset>, target: &TargetZone) -> DateTime<FixedOffset> {
+    return match target {
+        TargetZone::Local => {
+            let local = timestamp.with_timezone(&Local);
+            local.with_timezone(&local.offset().fix())
+        }
+        TargetZone::Fixed(offset) => timestamp.with_timezone(offset),
+    };
+}
+
+/// Converts timestamps with known offset into the target zone,
+/// returns the number of files left as is because their offset is unknown.
+pub fn normalize_timezones(items: &mut [FileMetadata], target: &TargetZone) -> usize {
+    let mut untouched: usize = 0;
+    for item in items.iter_mut() {
+        let instant: DateTime<FixedOffset> = match item.timestamp.instant() {
+            Some(i) => i,
+            None => {
+                untouched += 1;
+                continue;
+            }
+        };
+        item.timestamp = Timestamp::zoned(convert(&instant, target), item.timestamp.source);
+    }
+    return untouched;
+}
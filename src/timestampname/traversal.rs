@@ -0,0 +1,147 @@
+use std::collections::HashSet;
+use std::ffi::OsStr;
+use std::fs;
+use std::fs::DirEntry;
+use std::io;
+use std::io::Read;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+use std::path::PathBuf;
+
+use super::failures::Failure;
+
+pub struct TraversalOptions {
+    /// Descend into subdirectories, following symbolic links to directories.
+    pub recursive: bool,
+    /// List files and directories with names starting with a dot.
+    pub include_hidden: bool,
+    /// Glob patterns of files and directories to leave out.
+    pub exclusions: Vec<String>,
+}
+
+/// Lists files of the directory, with paths relative to it. Symbolic links to files are not listed,
+/// directories are listed only once regardless of how many links lead to them, which also breaks loops.
+pub fn list_files(root: &Path, options: &TraversalOptions) -> Result<Vec<PathBuf>, Failure> {
+    let mut files: Vec<PathBuf> = Vec::new();
+    let mut visited: HashSet<PathBuf> = HashSet::new();
+    list_directory(root, Path::new(""), options, &mut visited, &mut files)?;
+    return Ok(files);
+}
+
+fn list_directory(root: &Path,
+                  relative: &Path,
+                  options: &TraversalOptions,
+                  visited: &mut HashSet<PathBuf>,
+                  files: &mut Vec<PathBuf>) -> Result<(), Failure> {
+    let directory: PathBuf = root.join(relative);
+    let canonical: PathBuf = fs::canonicalize(&directory)
+        .map_err(|e| Failure::env_io(format!("Failed to resolve directory {}", directory.display()), e))?;
+    if !visited.insert(canonical) {
+        return Ok(());
+    }
+    let mut entries: Vec<DirEntry> = directory.read_dir()
+        .and_then(|iterator| iterator.collect::<Result<Vec<DirEntry>, io::Error>>())
+        .map_err(|e| Failure::env_io(format!("Failed to list contents of {}", directory.display()), e))?;
+    // stable listing order, numbering of files with equal timestamps depends on it:
+    entries.sort_by_key(|e| e.file_name());
+
+    let mut subdirectories: Vec<PathBuf> = Vec::new();
+    for entry in entries {
+        let path: PathBuf = relative.join(entry.file_name());
+        if !options.include_hidden && entry.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+        if options.exclusions.iter().any(|pattern| is_excluded(pattern, &path)) {
+            continue;
+        }
+        let file_type = entry.file_type()
+            .map_err(|e| Failure::env_io(format!("Failed to get file type of {}", path.display()), e))?;
+        if file_type.is_file() {
+            files.push(path);
+        } else if options.recursive && (file_type.is_dir() || file_type.is_symlink() && entry.path().is_dir()) {
+            subdirectories.push(path);
+        }
+    }
+    for subdirectory in subdirectories {
+        list_directory(root, &subdirectory, options, visited, files)?;
+    }
+    return Ok(());
+}
+
+/// Patterns with a slash are matched against the path relative to the root,
+/// patterns without one against the name alone, at any depth.
+fn is_excluded(pattern: &str, path: &Path) -> bool {
+    let text: String = match pattern.contains('/') {
+        true => path.to_string_lossy().to_string(),
+        false => path.file_name().map_or(String::new(), |n| n.to_string_lossy().to_string())
+    };
+    let pattern: Vec<char> = pattern.trim_end_matches('/').chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    return glob_matches(&pattern, &text);
+}
+
+/// `*` matches within a single path component, `**` across components, `?` any single character.
+fn glob_matches(pattern: &[char], text: &[char]) -> bool {
+    return match pattern.split_first() {
+        None => text.is_empty(),
+        Some(('*', rest)) if rest.first() == Some(&'*') => {
+            (0..=text.len()).any(|i| glob_matches(&rest[1..], &text[i..]))
+        }
+        Some(('*', rest)) => {
+            (0..=text.len())
+                .take_while(|i| *i == 0 || text[i - 1] != '/')
+                .any(|i| glob_matches(rest, &text[i..]))
+        }
+        Some(('?', rest)) => text.first().is_some_and(|c| *c != '/') && glob_matches(rest, &text[1..]),
+        Some((c, rest)) => text.first() == Some(c) && glob_matches(rest, &text[1..])
+    };
+}
+
+/// Files of the given directories and the given files themselves, in the order given,
+/// each file listed once. Paths are kept as given, relative to the working directory or absolute.
+pub fn collect_files(paths: &[PathBuf], options: &TraversalOptions) -> Result<Vec<PathBuf>, Failure> {
+    let mut files: Vec<PathBuf> = Vec::new();
+    for path in paths {
+        let metadata = fs::metadata(path)
+            .map_err(|e| Failure::env_io(format!("Failed to access {}", path.display()), e))?;
+        match metadata.is_dir() {
+            true => files.extend(list_files(path, options)?.into_iter().map(|f| path.join(f))),
+            false => files.push(path.to_path_buf())
+        }
+    }
+    // the same file given twice, directly or through its directory, would be renamed twice:
+    let mut seen: HashSet<PathBuf> = HashSet::new();
+    let mut unique: Vec<PathBuf> = Vec::with_capacity(files.len());
+    for file in files {
+        let canonical: PathBuf = fs::canonicalize(&file)
+            .map_err(|e| Failure::env_io(format!("Failed to resolve {}", file.display()), e))?;
+        if seen.insert(canonical) {
+            unique.push(file);
+        }
+    }
+    return Ok(unique);
+}
+
+/// Reads a list of paths from the file, or from standard input if the name is `-`.
+/// Paths are separated by NUL characters if there are any, by line breaks otherwise.
+pub fn read_file_list(source: &str) -> Result<Vec<PathBuf>, Failure> {
+    let content: Vec<u8> = match source {
+        "-" => {
+            let mut content: Vec<u8> = Vec::new();
+            io::stdin().read_to_end(&mut content)
+                .map_err(|e| Failure::env_io("Failed to read file list from standard input".to_string(), e))?;
+            content
+        }
+        _ => fs::read(source)
+            .map_err(|e| Failure::env_io(format!("Failed to read file list {}", source), e))?
+    };
+    let separator: u8 = match content.contains(&0) {
+        true => 0,
+        false => b'\n'
+    };
+    return Ok(content.split(|b| *b == separator)
+        .map(|entry| entry.strip_suffix(b"\r").unwrap_or(entry))
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| PathBuf::from(OsStr::from_bytes(entry)))
+        .collect());
+}
@@ -1,29 +1,69 @@
 use std::collections::HashSet;
+use std::fs;
 use std::path::Path;
+use std::path::PathBuf;
 
+use super::executor::already_linked;
 use super::failures::Failure;
 use super::RenameOperation;
+use super::Transfer;
 
-pub fn verify_operations(operations: &Vec<RenameOperation>,
-                         longest_source_name: usize) -> Result<(), Failure> {
-    let mut duplicates: HashSet<String> = HashSet::new();
+/// Links of previous runs in `replaced` are removed before the operations are executed,
+/// their names can be taken.
+pub fn verify_operations(operations: &[RenameOperation],
+                         longest_source_name: usize,
+                         transfer: Transfer,
+                         replaced: &[PathBuf]) -> Result<(), Failure> {
+    let longest_target_name: usize = operations.iter()
+        .map(|o| o.to.len())
+        .max()
+        .unwrap_or(0);
+    // destination folders and the names taken in them:
+    let mut folders: Vec<(&Path, HashSet<&str>)> = Vec::new();
     for operation in operations {
-        println!("    {:width$}    =>    {}",
+        println!("    {:width$}    =>    {:target_width$}    ({})",
                  operation.from,
                  operation.to,
-                 width = longest_source_name);
+                 operation.source,
+                 width = longest_source_name,
+                 target_width = longest_target_name);
+        let target = Path::new(&operation.to);
+        let folder: &Path = target.parent().unwrap_or(Path::new(""));
+        let name: &str = target.file_name().and_then(|n| n.to_str()).unwrap_or(&operation.to);
+        let position = match folders.iter().position(|f| f.0 == folder) {
+            Some(p) => p,
+            None => {
+                check_folder(folder)?;
+                folders.push((folder, HashSet::new()));
+                folders.len() - 1
+            }
+        };
         // check for target name duplicates:
-        if duplicates.contains(operation.to.as_str()) {
-            return Err(Failure::file_failure(operation.to.to_string(),
-                                             "Duplicate rename".to_string()));
+        if !folders[position].1.insert(name) {
+            return Err(Failure::conflict(operation.to.to_string(),
+                                         "Duplicate rename".to_string()));
         }
-        duplicates.insert(operation.to.to_string());
-        // check for renaming duplicates:
-        if operation.from != operation.to {
-            if Path::new(&operation.to).exists() {
-                return Err(Failure::file_failure(operation.to.to_string(),
-                                                 "File exists on file system".to_string()));
-            }
+        // check for renaming duplicates, dangling links included, links of a previous run can stay:
+        if operation.from != operation.to
+            && fs::symlink_metadata(target).is_ok()
+            && !already_linked(&operation.from, &operation.to, transfer)
+            && !replaced.iter().any(|r| r == target) {
+            return Err(Failure::conflict(operation.to.to_string(),
+                                         "File exists on file system".to_string()));
+        }
+    }
+    return Ok(());
+}
+
+// missing folders are created, but files cannot be moved into another file or below it,
+// ancestors above the first existing directory are directories as well:
+fn check_folder(folder: &Path) -> Result<(), Failure> {
+    for ancestor in folder.ancestors().filter(|a| !a.as_os_str().is_empty()) {
+        match fs::metadata(ancestor) {
+            Ok(metadata) if metadata.is_dir() => return Ok(()),
+            Ok(_) => return Err(Failure::conflict(ancestor.to_string_lossy().to_string(),
+                                                  "Destination folder is not a directory".to_string())),
+            Err(_) => {}
         }
     }
     return Ok(());